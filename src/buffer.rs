@@ -0,0 +1,761 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process,
+    rc::Rc,
+};
+
+use crate::{
+    format::FileFormat,
+    indent::{indentation_for, Indentation},
+    row::EditorRow,
+    syntax::{HighlightState, Syntax, SyntaxDatabase},
+    text::Text,
+    undo::{EditKind, Operation, UndoHistory},
+    window::View,
+};
+
+pub(crate) enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+    /// Up or down by a window's height of rows, scrolling along with the
+    /// cursor.
+    PageUp(usize),
+    PageDown(usize),
+    /// The first non-whitespace char of the row, or its start if the cursor
+    /// is already there.
+    Home,
+    End,
+    BufferStart,
+    BufferEnd,
+    /// To the start of the word before the cursor.
+    WordLeft,
+    /// To the end of the word after the cursor.
+    WordRight,
+}
+
+/// How many symlinks in a row are followed when saving, like the limit the
+/// OS puts on opening a path.
+const MAX_SYMLINKS: usize = 40;
+
+/// Chars that end a word when moving by words, unless the syntax has its
+/// own. Separators are only configured per syntax, so files without one
+/// always use these.
+const DEFAULT_WORD_SEPARATORS: &str = "`~!@#$%^&*()-=+[{]}\\|;:'\",.<>/?";
+
+/// What a word move treats a char as. A run of either words or separators
+/// counts as one word.
+#[derive(Clone, Copy, PartialEq)]
+enum CharClass {
+    Whitespace,
+    Separator,
+    Word,
+}
+
+/// A file being edited. Windows showing it each have their own cursor.
+pub(crate) struct Buffer {
+    /// Where the cursor was when the last window showing this buffer moved
+    /// on to another one, so switching back returns to the same place.
+    pub(crate) last_view: View,
+    pub(crate) text: Text,
+    /// The state each row ends inside of, for as many rows from the top as
+    /// have been highlighted. Rows in `highlight_dirty` and after it may be
+    /// out of date.
+    pub(crate) highlight_states: Vec<HighlightState>,
+    /// The first and last rows edited since highlighting last caught up.
+    pub(crate) highlight_dirty: Option<(usize, usize)>,
+    pub(crate) file_name: Option<String>,
+    pub(crate) format: FileFormat,
+    pub(crate) indentation: Indentation,
+    pub(crate) dirty: bool,
+    pub(crate) undo_history: UndoHistory,
+    pub(crate) syntax: Option<Rc<Syntax>>,
+}
+
+impl Buffer {
+    pub(crate) fn new() -> Self {
+        Self {
+            last_view: View::default(),
+            text: Text::new(),
+            highlight_states: Vec::new(),
+            highlight_dirty: None,
+            file_name: None,
+            format: FileFormat::default(),
+            indentation: Indentation::default(),
+            dirty: false,
+            undo_history: UndoHistory::default(),
+            syntax: None,
+        }
+    }
+
+    pub(crate) fn row_count(&self) -> usize {
+        self.text.row_count()
+    }
+
+    /// Renders a row, without highlighting it.
+    pub(crate) fn row(&self, row: usize) -> Option<EditorRow> {
+        if row < self.row_count() {
+            Some(EditorRow::from(
+                self.text.row(row),
+                self.indentation.tab_width,
+            ))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn move_cursor(&mut self, view: &mut View, direction: Direction) {
+        self.undo_history.seal();
+
+        match direction {
+            Direction::Left => {
+                if view.cursor_col != 0 {
+                    let row = self.row(view.cursor_row);
+                    view.cursor_col = row.map_or(0, |row| row.previous_grapheme(view.cursor_col));
+                } else if view.cursor_row > 0 {
+                    view.cursor_row -= 1;
+                    view.cursor_col = self.text.row_len(view.cursor_row);
+                }
+            }
+            Direction::Right => {
+                if let Some(row) = self.row(view.cursor_row) {
+                    if view.cursor_col < row.len() {
+                        view.cursor_col = row.next_grapheme(view.cursor_col);
+                    } else if view.cursor_col == row.len() {
+                        view.cursor_row += 1;
+                        view.cursor_col = 0;
+                    }
+                }
+            }
+            Direction::Up => {
+                if view.cursor_row != 0 {
+                    view.cursor_row -= 1;
+                }
+            }
+            Direction::Down => {
+                if view.cursor_row < self.row_count() {
+                    view.cursor_row += 1;
+                }
+            }
+            Direction::PageUp(rows) => {
+                view.cursor_row = view.cursor_row.saturating_sub(rows);
+                view.row_offset = view.row_offset.saturating_sub(rows);
+            }
+            Direction::PageDown(rows) => {
+                view.cursor_row = (view.cursor_row + rows).min(self.row_count());
+                view.row_offset = (view.row_offset + rows).min(view.cursor_row);
+            }
+            Direction::Home => {
+                let indent = self.row(view.cursor_row).map_or(0, |row| {
+                    row.text_raw
+                        .chars()
+                        .take_while(|char| char.is_whitespace())
+                        .count()
+                });
+                view.cursor_col = if view.cursor_col == indent { 0 } else { indent };
+            }
+            Direction::End => {
+                view.cursor_col = self.row(view.cursor_row).map_or(0, |row| row.len());
+            }
+            Direction::BufferStart => view.set_cursor((0, 0)),
+            Direction::BufferEnd => {
+                let last_row = self.row_count().saturating_sub(1);
+                let row_length = self.row(last_row).map_or(0, |row| row.len());
+                view.set_cursor((last_row, row_length));
+            }
+            Direction::WordLeft => view.set_cursor(self.previous_word(view)),
+            Direction::WordRight => view.set_cursor(self.next_word(view)),
+        }
+
+        let row = self.row(view.cursor_row);
+        match direction {
+            // Moving vertically keeps the cursor in the same screen column,
+            // even when passing through shorter lines or tabs
+            Direction::Up | Direction::Down | Direction::PageUp(_) | Direction::PageDown(_) => {
+                let render_col = *view.preferred_render_col.get_or_insert(view.render_col);
+                view.cursor_col = row.map_or(0, |row| row.char_index(render_col));
+            }
+            _ => {
+                view.preferred_render_col = None;
+                let row_length = row.map_or(0, |row| row.len());
+                if view.cursor_col > row_length {
+                    view.cursor_col = row_length;
+                }
+            }
+        }
+    }
+
+    fn char_class(&self, char: char) -> CharClass {
+        let separators = self
+            .syntax
+            .as_ref()
+            .and_then(|syntax| syntax.word_separators.as_deref())
+            .unwrap_or(DEFAULT_WORD_SEPARATORS);
+        if char.is_whitespace() {
+            CharClass::Whitespace
+        } else if separators.contains(char) {
+            CharClass::Separator
+        } else {
+            CharClass::Word
+        }
+    }
+
+    /// The start and end of the word at `col`, or of the run of separators
+    /// or whitespace it is in.
+    pub(crate) fn word_at(&self, row_index: usize, col: usize) -> (usize, usize) {
+        let row = match self.row(row_index) {
+            Some(row) if col < row.len() => row,
+            _ => return (col, col),
+        };
+
+        let class_at = |col| self.char_class(self.text.char_at(row_index, col));
+        let class = class_at(col);
+        let mut start = col;
+        while start > 0 && class_at(row.previous_grapheme(start)) == class {
+            start = row.previous_grapheme(start);
+        }
+        let mut end = col;
+        while end < row.len() && class_at(end) == class {
+            end = row.next_grapheme(end);
+        }
+        (start, end)
+    }
+
+    /// Where a word move right from the cursor ends: past any whitespace
+    /// and then the word after it, or the start of the next row from the
+    /// end of one.
+    fn next_word(&self, view: &View) -> (usize, usize) {
+        let (row_index, mut col) = (view.cursor_row, view.cursor_col);
+        let row = match self.row(row_index) {
+            Some(row) => row,
+            None => return (row_index, col),
+        };
+        if col >= row.len() {
+            return (row_index + 1, 0);
+        }
+
+        let class_at = |col| self.char_class(self.text.char_at(row_index, col));
+        while col < row.len() && class_at(col) == CharClass::Whitespace {
+            col = row.next_grapheme(col);
+        }
+        if col < row.len() {
+            let class = class_at(col);
+            while col < row.len() && class_at(col) == class {
+                col = row.next_grapheme(col);
+            }
+        }
+        (row_index, col)
+    }
+
+    /// Where a word move left from the cursor ends, the same way as
+    /// `next_word` but backwards.
+    fn previous_word(&self, view: &View) -> (usize, usize) {
+        let (row_index, mut col) = (view.cursor_row, view.cursor_col);
+        if col == 0 {
+            return match row_index.checked_sub(1) {
+                Some(row_index) => (row_index, self.text.row_len(row_index)),
+                None => (0, 0),
+            };
+        }
+        let row = match self.row(row_index) {
+            Some(row) => row,
+            None => return (row_index, col),
+        };
+
+        let class_before = |col| {
+            let start = row.previous_grapheme(col);
+            self.char_class(self.text.char_at(row_index, start))
+        };
+        while col > 0 && class_before(col) == CharClass::Whitespace {
+            col = row.previous_grapheme(col);
+        }
+        if col > 0 {
+            let class = class_before(col);
+            while col > 0 && class_before(col) == class {
+                col = row.previous_grapheme(col);
+            }
+        }
+        (row_index, col)
+    }
+
+    pub(crate) fn insert_char(&mut self, view: &mut View, char: char) {
+        let (row, col) = (view.cursor_row, view.cursor_col);
+        if row == self.row_count() {
+            self.edit(
+                view,
+                EditKind::Typing,
+                Operation::InsertRow { row },
+                (row, col),
+            );
+        }
+        self.edit(
+            view,
+            EditKind::Typing,
+            Operation::InsertChar { row, col, char },
+            (row, col + 1),
+        );
+    }
+
+    /// Inserts a tab, or spaces up to the next indent stop with soft tabs.
+    pub(crate) fn insert_tab(&mut self, view: &mut View) {
+        if !self.indentation.soft_tabs {
+            self.insert_char(view, '\t');
+            return;
+        }
+        let render_col = self
+            .row(view.cursor_row)
+            .map_or(0, |row| row.render_col(view.cursor_col));
+        let indent_size = self.indentation.indent_size;
+        for _ in 0..indent_size - render_col % indent_size {
+            self.insert_char(view, ' ');
+        }
+    }
+
+    pub(crate) fn insert_newline(&mut self, view: &mut View) {
+        let (row, col) = (view.cursor_row, view.cursor_col);
+        let operation = if row == self.row_count() {
+            Operation::InsertRow { row }
+        } else {
+            Operation::SplitLine { row, col }
+        };
+        self.edit(view, EditKind::Other, operation, (row + 1, 0));
+    }
+
+    pub(crate) fn delete_char(&mut self, view: &mut View) {
+        let (row, col) = (view.cursor_row, view.cursor_col);
+        if row == self.row_count() || (col == 0 && row == 0) {
+            return;
+        }
+
+        if col > 0 {
+            // The whole grapheme before the cursor goes, one char at a time
+            let start = self
+                .row(row)
+                .map_or(0, |editor_row| editor_row.previous_grapheme(col));
+            for col in (start..col).rev() {
+                let char = self.text.char_at(row, col);
+                self.edit(
+                    view,
+                    EditKind::Deleting,
+                    Operation::DeleteChar { row, col, char },
+                    (row, col),
+                );
+            }
+        } else {
+            // Join this line onto the end of the previous one
+            let previous_len = self.text.row_len(row - 1);
+            self.edit(
+                view,
+                EditKind::Deleting,
+                Operation::JoinLine {
+                    row: row - 1,
+                    col: previous_len,
+                },
+                (row - 1, previous_len),
+            );
+        }
+    }
+
+    pub(crate) fn delete_char_forward(&mut self, view: &mut View) {
+        let (row, col) = (view.cursor_row, view.cursor_col);
+        if row >= self.row_count() {
+            return;
+        }
+
+        let editor_row = self.row(row);
+        let end = editor_row
+            .as_ref()
+            .map_or(col, |editor_row| editor_row.next_grapheme(col));
+        if col < end {
+            for _ in col..end {
+                let char = self.text.char_at(row, col);
+                self.edit(
+                    view,
+                    EditKind::Deleting,
+                    Operation::DeleteChar { row, col, char },
+                    (row, col),
+                );
+            }
+        } else if row + 1 < self.row_count() {
+            self.edit(
+                view,
+                EditKind::Deleting,
+                Operation::JoinLine { row, col },
+                (row, col),
+            );
+        }
+    }
+
+    pub(crate) fn load_file(
+        &mut self,
+        path: &str,
+        syntax_database: &SyntaxDatabase,
+    ) -> io::Result<()> {
+        self.file_name = Some(path.to_string());
+        self.syntax = syntax_database.select(path);
+
+        // A path that doesn't exist yet is a new file, it gets created on save
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                self.indentation = indentation_for(Path::new(path), &self.text);
+                return Ok(());
+            }
+            Err(e) => return Err(e),
+        };
+        self.format = FileFormat::detect(BufReader::new(&file))?;
+        file.seek(SeekFrom::Start(0))?;
+        self.text = Text::load(BufReader::new(file), &self.format)?;
+        self.indentation = indentation_for(Path::new(path), &self.text);
+        self.invalidate_highlight(0);
+        self.undo_history = UndoHistory::default();
+        self.dirty = false;
+
+        Ok(())
+    }
+
+    /// Renames the buffer, which can change how it is highlighted and
+    /// indented.
+    pub(crate) fn set_file_name(&mut self, file_name: String, syntax_database: &SyntaxDatabase) {
+        self.syntax = syntax_database.select(&file_name);
+        self.indentation = indentation_for(Path::new(&file_name), &self.text);
+        self.file_name = Some(file_name);
+        self.invalidate_highlight(0);
+    }
+
+    pub(crate) fn save(&mut self) -> io::Result<usize> {
+        let path = match &self.file_name {
+            Some(file_name) => file_name.clone(),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "no file name")),
+        };
+
+        let written = write_atomic(Path::new(&path), &self.text, &self.format)?;
+        self.undo_history.mark_saved();
+        self.dirty = false;
+
+        Ok(written)
+    }
+
+    pub(crate) fn file_type(&self) -> Option<&str> {
+        self.syntax.as_ref().map(|syntax| syntax.name.as_str())
+    }
+}
+
+/// Writes `text` in `format` to a temporary file next to `path` and renames it over
+/// `path` once it is safely on disk, so a failed write never leaves a
+/// truncated file behind. A symlink is followed, so the file it points to
+/// is replaced rather than the link.
+fn write_atomic(path: &Path, text: &Text, format: &FileFormat) -> io::Result<usize> {
+    let path = resolve_symlinks(path)?;
+    let path = path.as_path();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let temp_path = dir.join(format!(
+        ".{}.kilors-{}.tmp",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+
+    let result = (|| {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)?;
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        let written = text.write_to(BufWriter::new(&file), format)?;
+        file.sync_all()?;
+        fs::rename(&temp_path, path)?;
+        Ok(written)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// The path of the file `path` really is, following symlinks even when the
+/// file at the end of them doesn't exist yet.
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Ok(path) => return Ok(path),
+        Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+        Err(_) => {}
+    }
+
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_SYMLINKS {
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                // A relative target is relative to the link's directory
+                let target = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(dir) => dir.join(target),
+                    None => target,
+                };
+            }
+            _ => return Ok(path),
+        }
+    }
+    Err(io::Error::other("too many levels of symbolic links"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROW_COUNT: usize = 1_000_000;
+
+    fn buffer_from(text: &str) -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.text = Text::load(text.as_bytes(), &FileFormat::default()).unwrap();
+        buffer
+    }
+
+    fn numbered_rows(count: usize) -> Buffer {
+        let text: String = (0..count).map(|row| format!("row {}\n", row)).collect();
+        buffer_from(&text)
+    }
+
+    #[test]
+    fn moves_down_through_every_row() {
+        let mut buffer = numbered_rows(ROW_COUNT);
+        let mut view = View::default();
+        for _ in 0..ROW_COUNT + 10 {
+            buffer.move_cursor(&mut view, Direction::Down);
+        }
+        assert_eq!(view.cursor_row, ROW_COUNT);
+
+        buffer.move_cursor(&mut view, Direction::Up);
+        buffer.move_cursor(&mut view, Direction::Right);
+        assert_eq!((view.cursor_row, view.cursor_col), (ROW_COUNT - 1, 1));
+    }
+
+    #[test]
+    fn scrolls_past_u16_rows() {
+        let buffer = numbered_rows(ROW_COUNT);
+        let mut view = View::default();
+        view.set_cursor((ROW_COUNT - 1, 0));
+        view.scroll(&buffer, 20, 80);
+        assert_eq!(view.row_offset, ROW_COUNT - 20);
+
+        view.set_cursor((70_000, 0));
+        view.scroll(&buffer, 20, 80);
+        assert_eq!(view.row_offset, 70_000);
+    }
+
+    #[test]
+    fn clamps_cursor_past_the_end() {
+        let buffer = numbered_rows(ROW_COUNT);
+        let mut view = View::default();
+        view.set_cursor((usize::MAX, usize::MAX));
+        view.clamp(&buffer);
+        assert_eq!((view.cursor_row, view.cursor_col), (ROW_COUNT, 0));
+    }
+
+    #[test]
+    fn edits_and_undoes_at_the_last_row() {
+        let mut buffer = numbered_rows(ROW_COUNT);
+        let mut view = View::default();
+        view.set_cursor((ROW_COUNT - 1, 0));
+        buffer.insert_char(&mut view, 'x');
+        buffer.insert_newline(&mut view);
+        assert_eq!(buffer.row_count(), ROW_COUNT + 1);
+        assert_eq!(buffer.text.row(ROW_COUNT - 1), "x");
+        assert_eq!(buffer.text.row(ROW_COUNT), format!("row {}", ROW_COUNT - 1));
+        assert_eq!(view.cursor_row, ROW_COUNT);
+
+        while buffer.undo(&mut view) {}
+        assert_eq!(buffer.row_count(), ROW_COUNT);
+        assert_eq!(
+            buffer.text.row(ROW_COUNT - 1),
+            format!("row {}", ROW_COUNT - 1)
+        );
+        assert_eq!((view.cursor_row, view.cursor_col), (ROW_COUNT - 1, 0));
+        assert!(!buffer.dirty);
+    }
+
+    #[test]
+    fn scrolls_along_a_long_row() {
+        let length = 100_000;
+        let buffer = buffer_from(&format!("{}\n", "a".repeat(length)));
+        let mut view = View::default();
+        view.set_cursor((0, length));
+        view.scroll(&buffer, 20, 80);
+        assert_eq!(view.render_col, length);
+        assert_eq!(view.col_offset, length - 79);
+    }
+
+    fn cursor_after(buffer: &mut Buffer, view: &mut View, direction: Direction) -> (usize, usize) {
+        buffer.move_cursor(view, direction);
+        (view.cursor_row, view.cursor_col)
+    }
+
+    #[test]
+    fn moves_by_words() {
+        let mut buffer = buffer_from("let x = foo(bar);\n  next\n");
+        let mut view = View::default();
+        let stops: Vec<_> = (0..8)
+            .map(|_| cursor_after(&mut buffer, &mut view, Direction::WordRight))
+            .collect();
+        assert_eq!(
+            stops,
+            [
+                (0, 3),
+                (0, 5),
+                (0, 7),
+                (0, 11),
+                (0, 12),
+                (0, 15),
+                (0, 17),
+                (1, 0)
+            ]
+        );
+
+        let stops: Vec<_> = (0..4)
+            .map(|_| cursor_after(&mut buffer, &mut view, Direction::WordLeft))
+            .collect();
+        assert_eq!(stops, [(0, 17), (0, 15), (0, 12), (0, 11)]);
+    }
+
+    #[test]
+    fn smart_home_goes_to_the_indent_first() {
+        let mut buffer = buffer_from("    indented\n");
+        let mut view = View::default();
+        assert_eq!(
+            cursor_after(&mut buffer, &mut view, Direction::End),
+            (0, 12)
+        );
+        assert_eq!(
+            cursor_after(&mut buffer, &mut view, Direction::Home),
+            (0, 4)
+        );
+        assert_eq!(
+            cursor_after(&mut buffer, &mut view, Direction::Home),
+            (0, 0)
+        );
+        assert_eq!(
+            cursor_after(&mut buffer, &mut view, Direction::Home),
+            (0, 4)
+        );
+    }
+
+    #[test]
+    fn pages_and_jumps_to_either_end() {
+        let mut buffer = numbered_rows(100);
+        let mut view = View::default();
+        view.scroll(&buffer, 20, 80);
+        assert_eq!(
+            cursor_after(&mut buffer, &mut view, Direction::PageDown(20)),
+            (20, 0)
+        );
+        assert_eq!(view.row_offset, 20);
+        assert_eq!(
+            cursor_after(&mut buffer, &mut view, Direction::BufferEnd),
+            (99, 6)
+        );
+        assert_eq!(
+            cursor_after(&mut buffer, &mut view, Direction::PageDown(20)),
+            (100, 0)
+        );
+        assert_eq!(
+            cursor_after(&mut buffer, &mut view, Direction::PageUp(20)),
+            (80, 0)
+        );
+        assert_eq!(
+            cursor_after(&mut buffer, &mut view, Direction::BufferStart),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn syntax_can_change_word_separators() {
+        let database = SyntaxDatabase::load_from(None);
+        let mut buffer = buffer_from("dev-dependencies = 1\n");
+        let mut view = View::default();
+        assert_eq!(
+            cursor_after(&mut buffer, &mut view, Direction::WordRight),
+            (0, 3)
+        );
+
+        buffer.syntax = database.select("Cargo.toml");
+        view.set_cursor((0, 0));
+        assert_eq!(
+            cursor_after(&mut buffer, &mut view, Direction::WordRight),
+            (0, 16)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_through_a_symlink_keeps_the_link() {
+        let dir = tempfile::tempdir().unwrap();
+        let target_dir = dir.path().join("real");
+        fs::create_dir(&target_dir).unwrap();
+        let target = target_dir.join("file.txt");
+        let link = dir.path().join("link.txt");
+        fs::write(&target, "old\n").unwrap();
+        std::os::unix::fs::symlink("real/file.txt", &link).unwrap();
+
+        let mut buffer = buffer_from("new\n");
+        buffer.file_name = Some(link.to_string_lossy().into_owned());
+        buffer.save().unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new\n");
+        // The temporary file was made next to the target and renamed away
+        assert_eq!(fs::read_dir(&target_dir).unwrap().count(), 1);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn saving_through_a_dangling_symlink_creates_its_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let target_dir = dir.path().join("real");
+        fs::create_dir(&target_dir).unwrap();
+        let target = target_dir.join("new.txt");
+        let link = dir.path().join("link.txt");
+        let link_to_link = dir.path().join("link2.txt");
+        std::os::unix::fs::symlink("real/new.txt", &link).unwrap();
+        std::os::unix::fs::symlink(&link, &link_to_link).unwrap();
+
+        let mut buffer = buffer_from("new\n");
+        buffer.file_name = Some(link_to_link.to_string_lossy().into_owned());
+        buffer.save().unwrap();
+
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert!(fs::symlink_metadata(&link_to_link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "new\n");
+        assert_eq!(fs::read_dir(&target_dir).unwrap().count(), 1);
+
+        // A loop of links can't be saved through
+        let looped = dir.path().join("loop.txt");
+        std::os::unix::fs::symlink("loop.txt", &looped).unwrap();
+        buffer.file_name = Some(looped.to_string_lossy().into_owned());
+        assert!(buffer.save().is_err());
+        assert!(fs::symlink_metadata(&looped)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+    }
+}
@@ -0,0 +1,355 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+const FILE_NAME: &str = ".editorconfig";
+
+/// The properties that `.editorconfig` files give `path`, with lowercase
+/// names and values. Files closer to `path` override the ones further up,
+/// and the search stops at a file with `root = true`.
+pub(crate) fn properties(path: &Path) -> HashMap<String, String> {
+    let path = match absolute(path) {
+        Some(path) => path,
+        None => return HashMap::new(),
+    };
+
+    let mut configs = Vec::new();
+    for dir in path.ancestors().skip(1) {
+        if let Ok(contents) = fs::read_to_string(dir.join(FILE_NAME)) {
+            let config = parse(&contents);
+            let root = config.root;
+            configs.push((dir.to_path_buf(), config));
+            if root {
+                break;
+            }
+        }
+    }
+
+    let mut properties = HashMap::new();
+    for (dir, config) in configs.iter().rev() {
+        let relative = match path.strip_prefix(dir) {
+            Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+            Err(_) => continue,
+        };
+        for section in &config.sections {
+            if section_matches(&section.glob, &relative) {
+                for (name, value) in &section.properties {
+                    properties.insert(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+    properties
+}
+
+fn absolute(path: &Path) -> Option<PathBuf> {
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().ok()?.join(path)
+    };
+    // The file itself might not exist yet, but its directory should
+    let dir = path.parent()?.canonicalize().ok()?;
+    Some(dir.join(path.file_name()?))
+}
+
+struct Section {
+    glob: String,
+    properties: Vec<(String, String)>,
+}
+
+struct Config {
+    root: bool,
+    sections: Vec<Section>,
+}
+
+fn parse(contents: &str) -> Config {
+    let mut config = Config {
+        root: false,
+        sections: Vec::new(),
+    };
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+
+        if let Some(glob) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            config.sections.push(Section {
+                glob: glob.to_string(),
+                properties: Vec::new(),
+            });
+            continue;
+        }
+
+        let (name, value) = match line.split_once('=') {
+            Some((name, value)) => (name.trim().to_lowercase(), value.trim().to_lowercase()),
+            None => continue,
+        };
+        match config.sections.last_mut() {
+            Some(section) => section.properties.push((name, value)),
+            // Only `root` belongs before the first section
+            None if name == "root" => config.root = value == "true",
+            None => {}
+        }
+    }
+
+    config
+}
+
+/// Matches a section's glob against a path relative to the directory of the
+/// `.editorconfig` file. A glob without a '/' matches files in any directory
+/// below it.
+fn section_matches(glob: &str, relative: &str) -> bool {
+    let glob = match glob.strip_prefix('/') {
+        Some(glob) => glob.to_string(),
+        None if glob.contains('/') => glob.to_string(),
+        None => format!("**/{}", glob),
+    };
+    let path: Vec<char> = relative.chars().collect();
+    expand_braces(&glob)
+        .iter()
+        .any(|pattern| glob_matches(&pattern.chars().collect::<Vec<_>>(), &path))
+}
+
+/// Turns the first `{a,b}` in a glob into one glob for each alternative,
+/// and then does the same for the rest of them. Number ranges such as
+/// `{1..3}` are left for `glob_matches`, since they can be huge.
+fn expand_braces(glob: &str) -> Vec<String> {
+    let chars: Vec<char> = glob.chars().collect();
+    let start = match chars.iter().position(|&char| char == '{') {
+        Some(start) => start,
+        None => return vec![glob.to_string()],
+    };
+
+    let mut depth = 0;
+    let mut end = None;
+    let mut commas = Vec::new();
+    for (index, &char) in chars.iter().enumerate().skip(start) {
+        match char {
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    end = Some(index);
+                    break;
+                }
+            }
+            ',' if depth == 1 => commas.push(index),
+            _ => {}
+        }
+    }
+    let end = match end {
+        Some(end) => end,
+        // An unclosed brace is just a brace
+        None => return vec![glob.to_string()],
+    };
+
+    let prefix: String = chars[..start].iter().collect();
+    let inner: String = chars[start + 1..end].iter().collect();
+    let suffix: String = chars[end + 1..].iter().collect();
+
+    if commas.is_empty() {
+        // Kept as it is, with only the braces after it expanded
+        return expand_braces(&suffix)
+            .iter()
+            .map(|suffix| format!("{}{{{}}}{}", prefix, inner, suffix))
+            .collect();
+    }
+    let mut alternatives = Vec::new();
+    let mut from = start + 1;
+    for &comma in commas.iter().chain(Some(&end)) {
+        alternatives.push(chars[from..comma].iter().collect::<String>());
+        from = comma + 1;
+    }
+
+    alternatives
+        .iter()
+        .flat_map(|alternative| expand_braces(&format!("{}{}{}", prefix, alternative, suffix)))
+        .collect()
+}
+
+/// Parses a `{1..3}` range after its opening brace, returning its bounds
+/// and how many chars of the pattern it took, including the closing '}'.
+fn number_range(pattern: &[char]) -> Option<(i64, i64, usize)> {
+    let end = pattern.iter().position(|&char| char == '}')?;
+    let text: String = pattern[..end].iter().collect();
+    let (from, to) = text.split_once("..")?;
+    let (from, to): (i64, i64) = (from.parse().ok()?, to.parse().ok()?);
+    Some((from.min(to), from.max(to), end + 1))
+}
+
+/// Matches `*`, `**`, `?`, `[...]` and `{1..3}` globs, where only `**`
+/// matches '/'.
+fn glob_matches(pattern: &[char], path: &[char]) -> bool {
+    match pattern.first() {
+        None => path.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            // "**/" also matches no directories at all
+            if rest.first() == Some(&'/') && glob_matches(&rest[1..], path) {
+                return true;
+            }
+            (0..=path.len()).any(|skip| glob_matches(rest, &path[skip..]))
+        }
+        Some('*') => {
+            let rest = &pattern[1..];
+            for skip in 0..=path.len() {
+                if glob_matches(rest, &path[skip..]) {
+                    return true;
+                }
+                if path.get(skip) == Some(&'/') {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => {
+            matches!(path.first(), Some(&char) if char != '/')
+                && glob_matches(&pattern[1..], &path[1..])
+        }
+        Some('[') => match char_class(&pattern[1..]) {
+            Some((matches, length)) => match path.first() {
+                Some(&char) if char != '/' && matches(char) => {
+                    glob_matches(&pattern[1 + length..], &path[1..])
+                }
+                _ => false,
+            },
+            None => path.first() == Some(&'[') && glob_matches(&pattern[1..], &path[1..]),
+        },
+        Some('{') => match number_range(&pattern[1..]) {
+            Some((from, to, length)) => {
+                let sign = usize::from(path.first() == Some(&'-'));
+                let digits = path[sign..]
+                    .iter()
+                    .take_while(|char| char.is_ascii_digit())
+                    .count();
+                (sign + 1..=sign + digits).any(|end| {
+                    let number: String = path[..end].iter().collect();
+                    number
+                        .parse()
+                        .is_ok_and(|number: i64| (from..=to).contains(&number))
+                        && glob_matches(&pattern[1 + length..], &path[end..])
+                })
+            }
+            None => path.first() == Some(&'{') && glob_matches(&pattern[1..], &path[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            path.first() == Some(&pattern[1]) && glob_matches(&pattern[2..], &path[1..])
+        }
+        Some(&char) => path.first() == Some(&char) && glob_matches(&pattern[1..], &path[1..]),
+    }
+}
+
+/// Parses the inside of a `[...]` class, returning what it matches and how
+/// many chars of the pattern it took, including the closing ']'.
+fn char_class(pattern: &[char]) -> Option<(impl Fn(char) -> bool, usize)> {
+    let negated = matches!(pattern.first(), Some('!') | Some('^'));
+    let start = if negated { 1 } else { 0 };
+    // A ']' right at the start is part of the class
+    let end = start
+        + 1
+        + pattern
+            .get(start + 1..)?
+            .iter()
+            .position(|&char| char == ']')?;
+    let class: Vec<char> = pattern[start..end].to_vec();
+
+    let matches = move |char: char| {
+        let mut found = false;
+        let mut index = 0;
+        while index < class.len() {
+            if index + 2 < class.len() && class[index + 1] == '-' {
+                found |= class[index] <= char && char <= class[index + 2];
+                index += 3;
+            } else {
+                found |= class[index] == char;
+                index += 1;
+            }
+        }
+        found != negated
+    };
+    Some((matches, end + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_globs() {
+        assert!(section_matches("*", "main.rs"));
+        assert!(section_matches("*", "src/main.rs"));
+        assert!(section_matches("*.rs", "src/main.rs"));
+        assert!(!section_matches("/*.rs", "src/main.rs"));
+        assert!(section_matches("src/*.rs", "src/main.rs"));
+        assert!(!section_matches("src/*.rs", "src/syntax/mod.rs"));
+        assert!(section_matches("src/**.rs", "src/syntax/mod.rs"));
+        assert!(section_matches("src/**/mod.rs", "src/mod.rs"));
+        assert!(section_matches("src/**/mod.rs", "src/a/b/mod.rs"));
+
+        assert!(section_matches("?.c", "a.c"));
+        assert!(!section_matches("?.c", "ab.c"));
+        assert!(!section_matches("a?b", "a/b"));
+
+        assert!(section_matches("[ab].c", "b.c"));
+        assert!(section_matches("[a-c].c", "c.c"));
+        assert!(!section_matches("[!ab].c", "a.c"));
+        assert!(section_matches("[!ab].c", "x.c"));
+
+        assert!(section_matches("*.{rs,toml}", "Cargo.toml"));
+        assert!(!section_matches("*.{rs,toml}", "README.md"));
+        assert!(section_matches("{src/*,tests/*}.rs", "tests/a.rs"));
+        assert!(section_matches("{single}.c", "{single}.c"));
+    }
+
+    #[test]
+    fn matches_number_ranges() {
+        assert!(section_matches("file{1..3}.txt", "file1.txt"));
+        assert!(section_matches("file{1..3}.txt", "file3.txt"));
+        assert!(!section_matches("file{1..3}.txt", "file4.txt"));
+        assert!(!section_matches("file{1..3}.txt", "file.txt"));
+        assert!(section_matches("file{3..1}.txt", "file2.txt"));
+        assert!(section_matches("file{-5..5}.txt", "file-3.txt"));
+        assert!(section_matches("{1..3}{4..6}", "25"));
+        assert!(section_matches("file{1..12}", "file12"));
+
+        // Huge ranges aren't expanded, so they are as quick as small ones
+        assert!(section_matches("{0..9000000000000000000}.log", "123.log"));
+        assert!(!section_matches("{0..9000000000000000000}.log", "x.log"));
+    }
+
+    #[test]
+    fn reads_files_up_to_the_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("project");
+        let src = project.join("src");
+        fs::create_dir_all(&src).unwrap();
+        fs::write(
+            dir.path().join(FILE_NAME),
+            "[*]\nindent_style = tab\ncharset = latin1\n",
+        )
+        .unwrap();
+        fs::write(
+            project.join(FILE_NAME),
+            "# comment\nroot = true\n\n[*]\nindent_style = space\nindent_size = 4\n\n[*.md]\nindent_size = 2\n",
+        )
+        .unwrap();
+        fs::write(src.join(FILE_NAME), "[*.rs]\nINDENT_SIZE = Tab\n").unwrap();
+
+        let rust = properties(&src.join("main.rs"));
+        assert_eq!(rust["indent_style"], "space");
+        assert_eq!(rust["indent_size"], "tab");
+        // The file above the root one is never read
+        assert!(!rust.contains_key("charset"));
+
+        let markdown = properties(&project.join("README.md"));
+        assert_eq!(markdown["indent_size"], "2");
+    }
+}
@@ -0,0 +1,435 @@
+use std::io::{self, BufRead};
+
+use crate::EditorState;
+
+const UTF8_BOM: &[u8] = b"\xef\xbb\xbf";
+/// Bytes that aren't valid UTF-8 are kept in the text as the private use
+/// chars from here on, one for each byte from 0x80 to 0xff.
+const RAW_BYTE_CHARS: u32 = 0x10ff00;
+/// How much of a file is checked for NUL bytes when guessing whether it is
+/// binary.
+const BINARY_CHECK_LENGTH: usize = 8000;
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Encoding {
+    Utf8,
+    /// Every byte is the char with the same value, so any file can be read
+    /// and written back unchanged.
+    Latin1,
+}
+
+/// How a file's text is laid out on disk, kept so saving writes it back the
+/// same way it was read.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct FileFormat {
+    pub(crate) line_ending: LineEnding,
+    pub(crate) final_newline: bool,
+    pub(crate) bom: bool,
+    pub(crate) encoding: Encoding,
+    /// Whether the UTF-8 text holds invalid bytes as raw byte chars.
+    pub(crate) raw_bytes: bool,
+    /// Whether the file looks like binary data rather than text. Only used
+    /// to warn about it, it doesn't change how the file is written.
+    pub(crate) binary: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            bom: false,
+            encoding: Encoding::Utf8,
+            raw_bytes: false,
+            binary: false,
+        }
+    }
+}
+
+impl FileFormat {
+    /// Reads through a file to work out its format. Line endings are only
+    /// CRLF when every line ends that way, otherwise the stray '\r's stay
+    /// in the text so they survive a round-trip.
+    ///
+    /// Text that isn't valid UTF-8 is taken to be Latin-1, unless it also
+    /// has valid multi-byte UTF-8 in it or looks binary. Then it stays UTF-8
+    /// and the invalid bytes are kept as they are.
+    pub(crate) fn detect(mut reader: impl BufRead) -> io::Result<Self> {
+        let mut format = Self::default();
+        let mut line = Vec::new();
+        let mut first = true;
+        let mut lf_count = 0;
+        let mut crlf_count = 0;
+        let mut length = 0;
+        let mut invalid = false;
+        let mut multi_byte = false;
+        // Raw byte chars already in the file would turn into bytes on save
+        let mut raw_byte_chars = false;
+
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            let mut text = &line[..];
+            if first && text.starts_with(UTF8_BOM) {
+                format.bom = true;
+                text = &text[UTF8_BOM.len()..];
+            }
+            first = false;
+
+            format.final_newline = text.ends_with(b"\n");
+            if text.ends_with(b"\r\n") {
+                crlf_count += 1;
+            } else if format.final_newline {
+                lf_count += 1;
+            }
+            if length < BINARY_CHECK_LENGTH {
+                let end = text.len().min(BINARY_CHECK_LENGTH - length);
+                format.binary |= text[..end].contains(&0);
+            }
+            length += text.len();
+
+            for chunk in text.utf8_chunks() {
+                invalid |= !chunk.invalid().is_empty();
+                for char in chunk.valid().chars() {
+                    multi_byte |= !char.is_ascii();
+                    raw_byte_chars |= raw_byte(char).is_some();
+                }
+            }
+        }
+
+        if crlf_count > 0 && lf_count == 0 {
+            format.line_ending = LineEnding::CrLf;
+        }
+        if invalid {
+            if (multi_byte || format.binary) && !raw_byte_chars {
+                format.raw_bytes = true;
+            } else {
+                format.encoding = Encoding::Latin1;
+            }
+        }
+        // A BOM only means something in UTF-8, otherwise it is just text
+        if format.encoding == Encoding::Latin1 {
+            format.bom = false;
+        }
+        // An empty file has no last line to end with a newline, but text
+        // typed into it should still get one
+        if first {
+            format.final_newline = true;
+        }
+
+        Ok(format)
+    }
+
+    pub(crate) fn line_ending(&self) -> &'static str {
+        match self.line_ending {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    /// Turns a line read from the file, including its line ending, into the
+    /// text of a row.
+    pub(crate) fn decode_line(&self, mut line: &[u8], first: bool) -> io::Result<String> {
+        if first && self.bom {
+            line = line.strip_prefix(UTF8_BOM).unwrap_or(line);
+        }
+        let newline = line.ends_with(b"\n");
+        line = line.strip_suffix(b"\n").unwrap_or(line);
+        // A '\r' on a last line without a '\n' is text, not a line ending
+        if newline && self.line_ending == LineEnding::CrLf {
+            line = line.strip_suffix(b"\r").unwrap_or(line);
+        }
+
+        match self.encoding {
+            Encoding::Utf8 if self.raw_bytes => {
+                let mut text = String::with_capacity(line.len());
+                for chunk in line.utf8_chunks() {
+                    text.push_str(chunk.valid());
+                    text.extend(chunk.invalid().iter().map(|&byte| raw_byte_char(byte)));
+                }
+                Ok(text)
+            }
+            Encoding::Utf8 => String::from_utf8(line.to_vec())
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Encoding::Latin1 => Ok(line.iter().map(|&byte| byte as char).collect()),
+        }
+    }
+
+    /// Appends the bytes of `text` to `bytes`, failing on chars the encoding
+    /// can't represent.
+    pub(crate) fn encode(&self, text: &str, bytes: &mut Vec<u8>) -> io::Result<()> {
+        match self.encoding {
+            Encoding::Utf8 if self.raw_bytes => {
+                for char in text.chars() {
+                    match raw_byte(char) {
+                        Some(byte) => bytes.push(byte),
+                        None => bytes.extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes()),
+                    }
+                }
+            }
+            Encoding::Utf8 => bytes.extend_from_slice(text.as_bytes()),
+            Encoding::Latin1 => {
+                for char in text.chars() {
+                    if let Some(byte) = raw_byte(char).filter(|_| self.raw_bytes) {
+                        bytes.push(byte);
+                    } else if char as u32 > 0xff {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!("'{}' can't be written as Latin-1", char),
+                        ));
+                    } else {
+                        bytes.push(char as u8);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn bom(&self) -> &'static [u8] {
+        if self.bom && self.encoding == Encoding::Utf8 {
+            UTF8_BOM
+        } else {
+            &[]
+        }
+    }
+
+    /// A short summary for the status bar, such as "utf-8 lf".
+    pub(crate) fn describe(&self) -> String {
+        let mut description = String::from(match self.encoding {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Latin1 => "latin-1",
+        });
+        if self.bom {
+            description.push_str("-bom");
+        }
+        if self.raw_bytes {
+            description.push_str("+raw");
+        }
+        description.push_str(match self.line_ending {
+            LineEnding::Lf => " lf",
+            LineEnding::CrLf => " crlf",
+        });
+        if !self.final_newline {
+            description.push_str(" noeol");
+        }
+        description
+    }
+
+    /// Changes one part of the format by name, as typed into the convert
+    /// prompt.
+    fn convert(&mut self, name: &str) -> Result<(), String> {
+        match name {
+            "lf" | "unix" => self.line_ending = LineEnding::Lf,
+            "crlf" | "dos" => self.line_ending = LineEnding::CrLf,
+            "utf-8" | "utf8" => self.encoding = Encoding::Utf8,
+            "latin-1" | "latin1" => {
+                self.encoding = Encoding::Latin1;
+                self.bom = false;
+            }
+            "bom" if self.encoding == Encoding::Utf8 => self.bom = true,
+            "bom" => return Err(String::from("Only UTF-8 files can have a BOM")),
+            "nobom" => self.bom = false,
+            "eol" => self.final_newline = true,
+            "noeol" => self.final_newline = false,
+            _ => return Err(format!("Unknown format: {}", name)),
+        }
+        Ok(())
+    }
+}
+
+fn raw_byte_char(byte: u8) -> char {
+    char::from_u32(RAW_BYTE_CHARS + byte as u32).unwrap()
+}
+
+/// The invalid byte a char stands for, if it is one of the raw byte chars.
+pub(crate) fn raw_byte(char: char) -> Option<u8> {
+    match (char as u32).checked_sub(RAW_BYTE_CHARS) {
+        Some(byte @ 0x80..=0xff) => Some(byte as u8),
+        _ => None,
+    }
+}
+
+impl EditorState {
+    pub(crate) fn convert_command(&mut self) -> crossterm::Result<()> {
+        let input = match self.prompt(
+            "Convert to: {} (lf, crlf, utf-8, latin-1, bom, nobom, eol, noeol)",
+            None,
+        )? {
+            Some(input) => input,
+            None => return Ok(()),
+        };
+
+        let (buffer, _) = self.buffer_and_view();
+        let mut format = buffer.format;
+        for name in input.split_whitespace() {
+            if let Err(message) = format.convert(&name.to_lowercase()) {
+                self.set_status_message(message);
+                return Ok(());
+            }
+        }
+
+        if format != buffer.format {
+            buffer.format = format;
+            buffer.undo_history.mark_unsaved();
+            buffer.dirty = true;
+        }
+        let message = format!("Format: {}", format.describe());
+        self.set_status_message(message);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crossterm::event::{KeyCode, KeyModifiers};
+
+    use super::*;
+    use crate::{buffer::Buffer, syntax::SyntaxDatabase, terminal::FakeTerminal, window::View};
+
+    fn load(path: &std::path::Path) -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer
+            .load_file(&path.to_string_lossy(), &SyntaxDatabase::load_from(None))
+            .unwrap();
+        buffer
+    }
+
+    /// Loads `bytes` from a file and saves it again without changing it.
+    fn round_trip(bytes: &[u8]) -> (FileFormat, Vec<u8>) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, bytes).unwrap();
+        let mut buffer = load(&path);
+        buffer.save().unwrap();
+        (buffer.format, fs::read(&path).unwrap())
+    }
+
+    #[test]
+    fn saves_files_back_unchanged() {
+        for bytes in [
+            &b"one\r\ntwo\r\n"[..],
+            b"one\r\ntwo\nthree\r\n",
+            b"one\ntwo",
+            b"one\r\ntwo",
+            b"a\r\nb\r",
+            b"a\r\n\r",
+            b"\xef\xbb\xbfone\ntwo\n",
+            b"\xef\xbb\xbf",
+            b"",
+            b"\n",
+            b"caf\xe9\n\xff\n",
+        ] {
+            assert_eq!(round_trip(bytes).1, bytes, "{:?}", bytes);
+        }
+    }
+
+    #[test]
+    fn detects_formats() {
+        assert_eq!(round_trip(b"a\r\nb\r\n").0.describe(), "utf-8 crlf");
+        // Stray '\r's stay in the text when not every line has one
+        assert_eq!(round_trip(b"a\r\nb\n").0.describe(), "utf-8 lf");
+        assert_eq!(round_trip(b"a\nb").0.describe(), "utf-8 lf noeol");
+        assert_eq!(round_trip(b"\xef\xbb\xbfa\n").0.describe(), "utf-8-bom lf");
+        assert_eq!(
+            round_trip(b"\xef\xbb\xbf").0.describe(),
+            "utf-8-bom lf noeol"
+        );
+        assert_eq!(round_trip(b"").0.describe(), "utf-8 lf");
+        assert_eq!(round_trip(b"caf\xe9\n").0.describe(), "latin-1 lf");
+    }
+
+    #[test]
+    fn keeps_invalid_bytes_in_utf8_text() {
+        let bytes = b"h\xc3\xa9llo \xff\xfe\n\xc3\n";
+        let (format, saved) = round_trip(bytes);
+        assert_eq!(saved, bytes);
+        assert!(format.raw_bytes);
+        assert!(!format.binary);
+        assert_eq!(format.describe(), "utf-8+raw lf");
+    }
+
+    #[test]
+    fn nul_bytes_make_a_file_binary() {
+        let bytes = b"ELF\0\x01\x80\n";
+        let (format, saved) = round_trip(bytes);
+        assert_eq!(saved, bytes);
+        assert!(format.binary);
+        // Binary files keep their invalid bytes raw instead of being Latin-1
+        assert!(format.raw_bytes);
+        assert!(format.encoding == Encoding::Utf8);
+    }
+
+    #[test]
+    fn latin1_cant_hold_chars_above_0xff() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, b"caf\xe9\n").unwrap();
+        let mut buffer = load(&path);
+        assert_eq!(buffer.text.row(0), "café");
+
+        let mut view = View::default();
+        buffer.insert_char(&mut view, 'ÿ');
+        buffer.save().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"\xffcaf\xe9\n");
+
+        buffer.insert_char(&mut view, '漢');
+        let error = buffer.save().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // The file is left as it was
+        assert_eq!(fs::read(&path).unwrap(), b"\xffcaf\xe9\n");
+        assert!(buffer.dirty);
+    }
+
+    #[test]
+    fn converting_makes_the_buffer_dirty() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.txt");
+        fs::write(&path, "a\n").unwrap();
+
+        let (mut terminal, _) = FakeTerminal::new(80, 10);
+        for input in ["lf", "crlf", "latin-1 bom"] {
+            terminal
+                .type_text(input)
+                .key(KeyCode::Enter, KeyModifiers::NONE);
+        }
+        let mut state =
+            EditorState::init(Box::new(terminal), SyntaxDatabase::load_from(None)).unwrap();
+        state
+            .open_files(&[path.to_string_lossy().into_owned()])
+            .unwrap();
+
+        // Converting to the format the file already has changes nothing
+        state.convert_command().unwrap();
+        assert!(!state.buffer().dirty);
+
+        let (buffer, view) = state.buffer_and_view();
+        buffer.insert_char(view, 'x');
+        state.convert_command().unwrap();
+        assert_eq!(state.status_message, "Format: utf-8 crlf");
+        let (buffer, view) = state.buffer_and_view();
+        assert!(buffer.dirty);
+        // Undoing the edit doesn't undo the conversion
+        assert!(buffer.undo(view));
+        assert!(buffer.dirty);
+        buffer.save().unwrap();
+        assert!(!buffer.dirty);
+        assert_eq!(fs::read(&path).unwrap(), b"a\r\n");
+
+        // Nothing changes when one of the parts can't be converted to
+        state.convert_command().unwrap();
+        assert_eq!(state.status_message, "Only UTF-8 files can have a BOM");
+        assert!(!state.buffer().dirty);
+    }
+}
@@ -0,0 +1,253 @@
+use std::{collections::HashMap, path::Path};
+
+use crate::{editorconfig, text::Text, EditorState, TAB_STOP_LENGTH};
+
+/// How many rows from the top of a file are looked at to detect its
+/// indentation.
+const DETECT_ROWS: usize = 1000;
+const DEFAULT_INDENT_SIZE: usize = 4;
+const MAX_INDENT_SIZE: usize = 16;
+
+/// How a buffer draws tabs and what the Tab key inserts.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct Indentation {
+    /// Whether Tab inserts spaces instead of a tab char.
+    pub(crate) soft_tabs: bool,
+    /// How many columns Tab indents by when inserting spaces.
+    pub(crate) indent_size: usize,
+    /// How many columns apart the tab stops are.
+    pub(crate) tab_width: usize,
+}
+
+impl Default for Indentation {
+    fn default() -> Self {
+        Self {
+            soft_tabs: false,
+            indent_size: TAB_STOP_LENGTH,
+            tab_width: TAB_STOP_LENGTH,
+        }
+    }
+}
+
+impl Indentation {
+    /// Guesses the indentation from the rows at the top of the text: tabs
+    /// if more rows start with a tab than with spaces, otherwise spaces by
+    /// the most common step between the indents of neighbouring rows.
+    pub(crate) fn detect(text: &Text) -> Option<Self> {
+        let mut tab_rows = 0;
+        let mut space_rows = 0;
+        let mut steps = [0; MAX_INDENT_SIZE + 1];
+        let mut previous_indent = 0;
+
+        for row in 0..text.row_count().min(DETECT_ROWS) {
+            let line = text.row(row);
+            if line.trim().is_empty() {
+                continue;
+            }
+            if line.starts_with('\t') {
+                tab_rows += 1;
+                continue;
+            }
+
+            let indent = line.chars().take_while(|&char| char == ' ').count();
+            if indent > 0 {
+                space_rows += 1;
+            }
+            let step = indent.abs_diff(previous_indent);
+            // Single spaces are more likely to line things up than indent
+            if (2..=MAX_INDENT_SIZE).contains(&step) {
+                steps[step] += 1;
+            }
+            previous_indent = indent;
+        }
+
+        if tab_rows == 0 && space_rows == 0 {
+            return None;
+        }
+        let mut indentation = Self::default();
+        if space_rows >= tab_rows {
+            indentation.soft_tabs = true;
+            indentation.indent_size = (2..=MAX_INDENT_SIZE)
+                .filter(|&step| steps[step] > 0)
+                .max_by_key(|&step| (steps[step], std::cmp::Reverse(step)))
+                .unwrap_or(DEFAULT_INDENT_SIZE);
+        }
+        Some(indentation)
+    }
+
+    /// Applies the `indent_style`, `indent_size` and `tab_width` properties
+    /// from `.editorconfig` files.
+    pub(crate) fn apply_editorconfig(&mut self, properties: &HashMap<String, String>) {
+        match properties.get("indent_style").map(String::as_str) {
+            Some("tab") => self.soft_tabs = false,
+            Some("space") => self.soft_tabs = true,
+            _ => {}
+        }
+
+        let indent_size = properties.get("indent_size").map(String::as_str);
+        let tab_width = properties
+            .get("tab_width")
+            .and_then(|value| parse_size(value));
+        match indent_size {
+            Some("tab") => self.indent_size = tab_width.unwrap_or(self.tab_width),
+            Some(value) => {
+                if let Some(size) = parse_size(value) {
+                    self.indent_size = size;
+                    // Without its own tab width, tabs are as wide as an indent
+                    if tab_width.is_none() {
+                        self.tab_width = size;
+                    }
+                }
+            }
+            None => {}
+        }
+        if let Some(width) = tab_width {
+            self.tab_width = width;
+        }
+    }
+
+    /// A short summary for the status bar, such as "spaces:4".
+    pub(crate) fn describe(&self) -> String {
+        if self.soft_tabs {
+            format!("spaces:{}", self.indent_size)
+        } else {
+            format!("tabs:{}", self.tab_width)
+        }
+    }
+
+    /// Changes the indentation from words typed into the indentation
+    /// prompt: "tabs", "spaces", a number for the indent size, and "tw=N"
+    /// for the tab width.
+    fn set(&mut self, word: &str) -> Result<(), String> {
+        match word {
+            "tabs" | "tab" => self.soft_tabs = false,
+            "spaces" | "space" => self.soft_tabs = true,
+            word => {
+                if let Some(width) = word.strip_prefix("tw=") {
+                    self.tab_width =
+                        parse_size(width).ok_or(format!("Bad tab width: {}", width))?;
+                } else {
+                    let size = parse_size(word).ok_or(format!("Unknown indentation: {}", word))?;
+                    self.indent_size = size;
+                    if !self.soft_tabs {
+                        self.tab_width = size;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_size(value: &str) -> Option<usize> {
+    value
+        .parse()
+        .ok()
+        .filter(|size| (1..=MAX_INDENT_SIZE).contains(size))
+}
+
+/// Works out the indentation of a file being opened, from its contents and
+/// then from any `.editorconfig` files, which win.
+pub(crate) fn indentation_for(path: &Path, text: &Text) -> Indentation {
+    let mut indentation = Indentation::detect(text).unwrap_or_default();
+    indentation.apply_editorconfig(&editorconfig::properties(path));
+    indentation
+}
+
+impl EditorState {
+    pub(crate) fn indentation_command(&mut self) -> crossterm::Result<()> {
+        let input = match self.prompt(
+            "Indentation: {} (tabs, spaces, N = indent size, tw=N = tab width)",
+            None,
+        )? {
+            Some(input) => input,
+            None => return Ok(()),
+        };
+
+        let (buffer, _) = self.buffer_and_view();
+        let mut indentation = buffer.indentation;
+        for word in input.split_whitespace() {
+            if let Err(message) = indentation.set(&word.to_lowercase()) {
+                self.set_status_message(message);
+                return Ok(());
+            }
+        }
+        buffer.indentation = indentation;
+        let message = format!("Indentation: {}", indentation.describe());
+        self.set_status_message(message);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::FileFormat;
+
+    fn detect(text: &str) -> Option<Indentation> {
+        Indentation::detect(&Text::load(text.as_bytes(), &FileFormat::default()).unwrap())
+    }
+
+    fn editorconfig(indentation: &mut Indentation, properties: &[(&str, &str)]) {
+        let properties = properties
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        indentation.apply_editorconfig(&properties);
+    }
+
+    #[test]
+    fn detects_tabs_and_spaces() {
+        assert!(detect("a\nb\n").is_none());
+
+        let tabs = detect("fn a() {\n\tb();\n\tif c {\n\t\td();\n\t}\n}\n").unwrap();
+        assert!(!tabs.soft_tabs);
+
+        let spaces = detect("a:\n  b:\n    c\n  d\n").unwrap();
+        assert!(spaces.soft_tabs);
+        assert_eq!(spaces.indent_size, 2);
+
+        let spaces = detect("a\n    b\n        c\n    d\n         lined up\n").unwrap();
+        assert_eq!(spaces.indent_size, 4);
+
+        // The more common of the two wins
+        let mixed = detect("a\n\tb\n\tc\n  d\n").unwrap();
+        assert!(!mixed.soft_tabs);
+        let mixed = detect("a\n\tb\n  c\n  d\n").unwrap();
+        assert!(mixed.soft_tabs);
+    }
+
+    #[test]
+    fn applies_editorconfig() {
+        let mut indentation = detect("a\n  b\n").unwrap();
+        editorconfig(&mut indentation, &[("indent_style", "tab")]);
+        assert!(!indentation.soft_tabs);
+
+        let mut indentation = Indentation::default();
+        editorconfig(
+            &mut indentation,
+            &[("indent_style", "space"), ("indent_size", "2")],
+        );
+        assert!(indentation.soft_tabs);
+        assert_eq!((indentation.indent_size, indentation.tab_width), (2, 2));
+
+        editorconfig(
+            &mut indentation,
+            &[("indent_size", "tab"), ("tab_width", "8")],
+        );
+        assert_eq!((indentation.indent_size, indentation.tab_width), (8, 8));
+
+        // Without a tab width, an indent is as wide as a tab already is
+        let mut indentation = Indentation {
+            tab_width: 3,
+            ..Indentation::default()
+        };
+        editorconfig(&mut indentation, &[("indent_size", "tab")]);
+        assert_eq!(indentation.indent_size, 3);
+
+        // Sizes that make no sense are ignored
+        editorconfig(&mut indentation, &[("indent_size", "0")]);
+        assert_eq!(indentation.indent_size, 3);
+    }
+}
@@ -1,259 +1,1186 @@
 use std::{
-    fs::File,
-    io::{stdout, BufRead, BufReader, Write},
+    env, panic,
     process::exit,
-    usize,
+    time::{Duration, Instant},
 };
 
-use crossterm::{cursor::{Hide, MoveTo, Show}, event::{read, Event, KeyCode, KeyEvent}, execute, terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode, size}};
+mod buffer;
+mod editorconfig;
+mod format;
+mod indent;
+mod mouse;
+mod prompt;
+mod row;
+mod screen;
+mod search;
+mod syntax;
+mod terminal;
+mod text;
+mod undo;
+mod window;
 
-const TAB_STOP_LENGTH: u16 = 8;
+use buffer::{Buffer, Direction};
+use mouse::Click;
+use prompt::PromptHistory;
+use screen::{Grid, Screen, Style};
+use syntax::{Highlight, SyntaxDatabase};
+use terminal::{CrosstermTerminal, Terminal};
+use window::{Layout, Rect, View, Window};
 
-struct EditorRow {
-    text_raw: String,
-    text_render: Vec<char>,
-}
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
 
-impl EditorRow {
-    fn from(str: String) -> Self {
-        let mut row = Self {
-            text_raw: str,
-            text_render: Vec::new(),
-        };
-        row.update();
-        row
-    }
-
-    fn update(&mut self) {
-        self.text_render = Vec::new();
-        let mut index = 0;
-        for char in self.text_raw.chars() {
-            match char {
-                '\t' => {
-                    self.text_render.push(' ');
-                    index += 1;
-                    let tab_width = TAB_STOP_LENGTH - (index % TAB_STOP_LENGTH);
-                    for i in 0..tab_width {
-                        self.text_render.push(' ');
-                    }
-                }
-                char => {
-                    self.text_render.push(char);
-                    index += 1;
-                }
-            }
-        }
-    }
-}
+const TAB_STOP_LENGTH: usize = 8;
+const STATUS_MESSAGE_TIMEOUT: Duration = Duration::from_secs(5);
+const QUIT_TIMES: u8 = 3;
 
 struct EditorState {
-    cursor_row: u16,
-    cursor_col: u16,
-    row_offset: u16,
-    col_offset: u16,
+    // The size of the whole terminal. The last row is the message bar, and
+    // the windows share the rest.
     screen_rows: u16,
     screen_cols: u16,
-    rows: Vec<EditorRow>,
-    file_name: String,
+    // Never empty, there is always at least one buffer open
+    buffers: Vec<Buffer>,
+    // Never empty either, every window is a leaf of the layout
+    windows: Vec<Window>,
+    focused_window: usize,
+    layout: Layout,
+    status_message: String,
+    status_message_time: Instant,
+    quit_times: u8,
+    should_quit: bool,
+    search_query: Option<String>,
+    search_last_match: Option<(usize, usize)>,
+    prompt_history: PromptHistory,
+    prompt_cursor: Option<u16>,
+    last_click: Option<Click>,
+    syntax_database: SyntaxDatabase,
+    screen: Screen,
+    terminal: Box<dyn Terminal>,
 }
 
 impl EditorState {
-    fn init() -> crossterm::Result<Self> {
-        let (columns, rows) = size()?;
+    fn init(
+        terminal: Box<dyn Terminal>,
+        syntax_database: SyntaxDatabase,
+    ) -> crossterm::Result<Self> {
+        let (columns, rows) = terminal.size()?;
         Ok(Self {
-            cursor_row: 0,
-            cursor_col: 0,
-            row_offset: 0,
-            col_offset: 0,
             screen_rows: rows,
             screen_cols: columns,
-            rows: Vec::new(),
-            file_name: String::new(),
+            buffers: vec![Buffer::new()],
+            windows: vec![Window {
+                buffer: 0,
+                view: View::default(),
+            }],
+            focused_window: 0,
+            layout: Layout::Window(0),
+            status_message: String::new(),
+            status_message_time: Instant::now(),
+            quit_times: QUIT_TIMES,
+            should_quit: false,
+            search_query: None,
+            search_last_match: None,
+            prompt_history: PromptHistory::default(),
+            prompt_cursor: None,
+            last_click: None,
+            syntax_database,
+            screen: Screen::default(),
+            terminal,
         })
     }
 
-    fn move_cursor(&mut self, direction: Direction) {
-        let row = self.rows.get(self.cursor_row as usize);
+    fn window(&self) -> &Window {
+        &self.windows[self.focused_window]
+    }
 
-        match direction {
-            Direction::Left => {
-                if self.cursor_col != 0 {
-                    self.cursor_col -= 1;
-                } else if self.cursor_row > 0 {
-                    self.cursor_row -= 1;
-                    self.cursor_col = self.rows[self.cursor_row as usize].text_render.len() as u16;
-                }
-            }
-            Direction::Right => {
-                if let Some(row) = row {
-                    if (self.cursor_col as usize) < row.text_render.len() {
-                        self.cursor_col += 1;
-                    } else if (self.cursor_col as usize) == row.text_render.len() {
-                        self.cursor_row += 1;
-                        self.cursor_col = 0;
-                    }
-                }
-            }
-            Direction::Up => {
-                if self.cursor_row != 0 {
-                    self.cursor_row -= 1;
+    fn buffer(&self) -> &Buffer {
+        &self.buffers[self.window().buffer]
+    }
+
+    /// The focused window's buffer and its cursor in it.
+    fn buffer_and_view(&mut self) -> (&mut Buffer, &mut View) {
+        let window = &mut self.windows[self.focused_window];
+        (&mut self.buffers[window.buffer], &mut window.view)
+    }
+
+    fn open_files(&mut self, file_names: &[String]) -> std::io::Result<()> {
+        if file_names.is_empty() {
+            return Ok(());
+        }
+
+        self.buffers.clear();
+        for file_name in file_names {
+            let mut buffer = Buffer::new();
+            buffer.load_file(file_name, &self.syntax_database)?;
+            self.buffers.push(buffer);
+        }
+        self.windows[0].buffer = 0;
+
+        Ok(())
+    }
+
+    fn switch_buffer(&mut self, index: usize) -> crossterm::Result<()> {
+        let window = &mut self.windows[self.focused_window];
+        self.buffers[window.buffer].last_view = window.view;
+        window.buffer = index;
+        window.view = self.buffers[index].last_view;
+        self.update_title()?;
+        let buffer = self.buffer();
+        let message = format!(
+            "Buffer {}/{}: {}",
+            index + 1,
+            self.buffers.len(),
+            buffer.file_name.as_deref().unwrap_or("[No Name]")
+        );
+        self.set_status_message(message);
+
+        Ok(())
+    }
+
+    fn cycle_buffer(&mut self, forward: bool) -> crossterm::Result<()> {
+        let count = self.buffers.len();
+        if count == 1 {
+            self.set_status_message(String::from("No other buffers"));
+            return Ok(());
+        }
+        let index = if forward {
+            (self.window().buffer + 1) % count
+        } else {
+            (self.window().buffer + count - 1) % count
+        };
+        self.switch_buffer(index)
+    }
+
+    fn pick_buffer(&mut self) -> crossterm::Result<()> {
+        let list: Vec<String> = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(index, buffer)| {
+                format!(
+                    "{}:{}{}",
+                    index + 1,
+                    buffer.file_name.as_deref().unwrap_or("[No Name]"),
+                    if buffer.dirty { "*" } else { "" }
+                )
+            })
+            .collect();
+        let prompt = format!("Buffers {} - switch to: {{}}", list.join(" "));
+        let input = match self.prompt(&prompt, None)? {
+            Some(input) => input,
+            None => return Ok(()),
+        };
+        let input = input.trim();
+
+        // Either a buffer number, or part of a file name that only one
+        // buffer matches
+        let index = match input.parse::<usize>() {
+            Ok(number) if number >= 1 && number <= self.buffers.len() => Some(number - 1),
+            _ => {
+                let matches: Vec<usize> = self
+                    .buffers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, buffer)| {
+                        buffer
+                            .file_name
+                            .as_deref()
+                            .is_some_and(|file_name| file_name.contains(input))
+                    })
+                    .map(|(index, _)| index)
+                    .collect();
+                if matches.len() == 1 {
+                    Some(matches[0])
+                } else {
+                    None
                 }
             }
-            Direction::Down => {
-                if (self.cursor_row as usize) < self.rows.len() {
-                    self.cursor_row += 1;
-                }
+        };
+
+        match index {
+            Some(index) => self.switch_buffer(index),
+            None => {
+                self.set_status_message(format!("No single buffer matches: {}", input));
+                Ok(())
             }
         }
+    }
 
-        let row = self.rows.get(self.cursor_row as usize);
-        let row_length = row.map_or(0, |row| row.text_render.len()) as u16;
-        if self.cursor_col > row_length {
-            self.cursor_col = row_length;
-        }
+    fn update_title(&mut self) -> crossterm::Result<()> {
+        let title = match &self.buffer().file_name {
+            Some(file_name) => format!("kilors - {}", file_name),
+            None => String::from("kilors"),
+        };
+        self.terminal.set_title(&title)
     }
 
-    fn handle_keypress(&mut self, key: KeyEvent) {
+    fn handle_keypress(&mut self, key: KeyEvent) -> crossterm::Result<()> {
+        // A page is a window's height, at least one row even in a tiny one
+        let page = self.window_text_rows().max(1);
+        let (buffer, view) = self.buffer_and_view();
+        view.anchor = None;
+        view.free_scroll = false;
+        if !matches!(
+            key.code,
+            KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown
+        ) {
+            view.preferred_render_col = None;
+        }
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+
         match key.code {
-            KeyCode::Left => self.move_cursor(Direction::Left),
-            KeyCode::Right => self.move_cursor(Direction::Right),
-            KeyCode::Up => self.move_cursor(Direction::Up),
-            KeyCode::Down => self.move_cursor(Direction::Down),
-            KeyCode::Esc => {
-                cleanup();
-                exit(0);
+            KeyCode::Left if control => buffer.move_cursor(view, Direction::WordLeft),
+            KeyCode::Right if control => buffer.move_cursor(view, Direction::WordRight),
+            KeyCode::Home if control => buffer.move_cursor(view, Direction::BufferStart),
+            KeyCode::End if control => buffer.move_cursor(view, Direction::BufferEnd),
+            KeyCode::Left => buffer.move_cursor(view, Direction::Left),
+            KeyCode::Right => buffer.move_cursor(view, Direction::Right),
+            KeyCode::Up => buffer.move_cursor(view, Direction::Up),
+            KeyCode::Down => buffer.move_cursor(view, Direction::Down),
+            KeyCode::PageUp => buffer.move_cursor(view, Direction::PageUp(page)),
+            KeyCode::PageDown => buffer.move_cursor(view, Direction::PageDown(page)),
+            KeyCode::Home => buffer.move_cursor(view, Direction::Home),
+            KeyCode::End => buffer.move_cursor(view, Direction::End),
+            KeyCode::Enter => buffer.insert_newline(view),
+            KeyCode::Backspace => buffer.delete_char(view),
+            KeyCode::Delete => buffer.delete_char_forward(view),
+            KeyCode::Tab => buffer.insert_tab(view),
+            KeyCode::Char('s') if control => self.save_command()?,
+            KeyCode::Char('f') if control => self.find()?,
+            KeyCode::Char('z') if control => self.undo_command(),
+            KeyCode::Char('y') if control => self.redo_command(),
+            KeyCode::Char('g') if control => self.goto_line()?,
+            KeyCode::Char('n') if control => self.cycle_buffer(true)?,
+            KeyCode::Char('p') if control => self.cycle_buffer(false)?,
+            KeyCode::Char('b') if control => self.pick_buffer()?,
+            KeyCode::Char('w') if control => self.window_command()?,
+            KeyCode::Char('e') if control => self.convert_command()?,
+            KeyCode::Char('t') if control => self.indentation_command()?,
+            KeyCode::Char(char) if !control && !key.modifiers.contains(KeyModifiers::ALT) => {
+                buffer.insert_char(view, char)
+            }
+            KeyCode::Char('q') if control => {
+                let dirty = self.buffers.iter().filter(|buffer| buffer.dirty).count();
+                if dirty > 0 && self.quit_times > 1 {
+                    self.quit_times -= 1;
+                    let files = if dirty == 1 {
+                        String::from("File has")
+                    } else {
+                        format!("{} files have", dirty)
+                    };
+                    self.set_status_message(format!(
+                        "WARNING!!! {} unsaved changes. Press Ctrl-Q {} more times to quit.",
+                        files, self.quit_times
+                    ));
+                    return Ok(());
+                }
+                self.should_quit = true;
             }
             _ => {}
         }
+
+        self.quit_times = QUIT_TIMES;
+
+        Ok(())
     }
 
-    fn load_file(&mut self, path: &str) -> std::io::Result<()> {
-        let file = File::open(path)?;
-        let lines = BufReader::new(file).lines();
+    fn save_command(&mut self) -> crossterm::Result<()> {
+        if self.buffer().file_name.is_none() {
+            match self.prompt("Save as: {} (ESC to cancel)", None)? {
+                Some(file_name) => {
+                    let index = self.window().buffer;
+                    let buffer = &mut self.buffers[index];
+                    buffer.set_file_name(file_name, &self.syntax_database);
+                    self.update_title()?;
+                }
+                None => {
+                    self.set_status_message(String::from("Save aborted"));
+                    return Ok(());
+                }
+            }
+        }
 
-        for line in lines {
-            let line = line?;
-            let row = EditorRow::from(line);
-            self.rows.push(row);
+        match self.buffer_and_view().0.save() {
+            Ok(bytes) => self.set_status_message(format!("Saved {} bytes", bytes)),
+            Err(e) => self.set_status_message(format!("Can't save! I/O error: {}", e)),
         }
 
         Ok(())
     }
 
-    fn scroll(&mut self) {
-        if self.cursor_row < self.row_offset {
-            self.row_offset = self.cursor_row
-        }
-        if self.cursor_row >= self.row_offset + self.screen_rows {
-            self.row_offset = self.cursor_row - self.screen_rows + 1;
+    fn undo_command(&mut self) {
+        let (buffer, view) = self.buffer_and_view();
+        if !buffer.undo(view) {
+            self.set_status_message(String::from("Nothing to undo"));
         }
+    }
 
-        if self.cursor_col < self.col_offset {
-            self.col_offset = self.cursor_col;
+    fn redo_command(&mut self) {
+        let (buffer, view) = self.buffer_and_view();
+        if !buffer.redo(view) {
+            self.set_status_message(String::from("Nothing to redo"));
         }
-        if self.cursor_col >= self.col_offset + self.screen_cols {
-            self.col_offset = self.cursor_col - self.screen_cols + 1;
+    }
+
+    fn goto_line(&mut self) -> crossterm::Result<()> {
+        let input = match self.prompt("Go to line: {}", None)? {
+            Some(input) => input,
+            None => return Ok(()),
+        };
+
+        match input.trim().parse::<usize>() {
+            Ok(line) if line > 0 => {
+                let (buffer, view) = self.buffer_and_view();
+                let last_row = buffer.row_count().saturating_sub(1);
+                view.set_cursor(((line - 1).min(last_row), 0));
+            }
+            _ => self.set_status_message(format!("Invalid line number: {}", input)),
         }
+
+        Ok(())
     }
 
-    fn draw_rows(&self) -> crossterm::Result<()> {
-        for row_num in 0..self.screen_rows {
-            let file_row = row_num + self.row_offset;
+    fn draw_window(&self, frame: &mut Grid, index: usize, rect: Rect) {
+        let window = &self.windows[index];
+        let buffer = &self.buffers[window.buffer];
+        let view = &window.view;
+        let text_rows = rect.height.saturating_sub(1);
+        let right = rect.x + rect.width;
 
-            let row_text = if file_row as usize >= self.rows.len() {
-                String::from("~")
-            } else {
-                let text_render = &self.rows[file_row as usize].text_render;
-                
-                if text_render.len() > self.col_offset as usize {
-                    let mut len = text_render.len() - self.col_offset as usize;
-                    len = if len > self.screen_cols as usize {
-                        self.screen_cols as usize
+        for row_num in 0..text_rows {
+            let file_row = row_num as usize + view.row_offset;
+            let y = rect.y + row_num;
+
+            let mut row = match buffer.highlighted_row(file_row) {
+                Some(row) => row,
+                None => {
+                    frame.print(rect.x, y, right, "~", Style::default());
+                    continue;
+                }
+            };
+            let start = view.col_offset.min(row.render_len());
+            let end = (start + rect.width as usize).min(row.render_len());
+
+            let mut highlight = std::mem::take(&mut row.highlight);
+            if index == self.focused_window {
+                for (from, to) in self.search_highlights(file_row) {
+                    for class in &mut highlight[from..to] {
+                        *class = Highlight::Match;
+                    }
+                }
+            }
+            if let Some((start, end)) = view.selection() {
+                if (start.0..=end.0).contains(&file_row) {
+                    let from = if file_row == start.0 {
+                        row.render_col(start.1.min(row.len()))
+                    } else {
+                        0
+                    };
+                    let to = if file_row == end.0 {
+                        row.render_col(end.1.min(row.len()))
                     } else {
-                        len
+                        row.render_len()
                     };
-                    let slice_range = self.col_offset as usize..self.col_offset as usize + len;
-                    text_render[slice_range].into_iter().collect()
+                    for class in &mut highlight[from..to] {
+                        *class = Highlight::Selection;
+                    }
+                }
+            }
+
+            for (col, &class) in (start..end).zip(&highlight[start..end]) {
+                let x = rect.x + (col - start) as u16;
+                let style = class.style();
+                // A wide grapheme cut off by either edge of the window is
+                // drawn as spaces, so it can't spill over the edge
+                let cell = row.cell(col);
+                let width = row.cell_width(col);
+                if cell.is_empty() {
+                    if col == start {
+                        frame.put(x, y, " ", 1, style);
+                    }
+                } else if col + width > end {
+                    frame.fill(x, y, rect.x + (end - start) as u16, style);
                 } else {
-                    String::new()
+                    frame.put(x, y, cell, width, style);
                 }
-            };
-            execute!(stdout(), Clear(ClearType::CurrentLine))?;
-            stdout().write(row_text.as_bytes())?;
-            if row_num < self.screen_rows - 1{
-                stdout().write("\r\n".as_bytes())?;
             }
         }
 
-        stdout().flush()?;
+        if rect.height > 0 {
+            self.draw_status_bar(frame, index, rect);
+        }
+    }
 
-        Ok(())
+    fn draw_status_bar(&self, frame: &mut Grid, index: usize, rect: Rect) {
+        let window = &self.windows[index];
+        let buffer = &self.buffers[window.buffer];
+        let file_name = buffer.file_name.as_deref().unwrap_or("[No Name]");
+        let buffer_number = if self.buffers.len() > 1 {
+            format!("[{}/{}] ", window.buffer + 1, self.buffers.len())
+        } else {
+            String::new()
+        };
+        let left = format!(
+            "{}{:.20} - {} lines{}",
+            buffer_number,
+            file_name,
+            buffer.row_count(),
+            if buffer.dirty { " (modified)" } else { "" }
+        );
+        let right = format!(
+            "{} | {} | {} | {}/{}",
+            buffer.file_type().unwrap_or("no ft"),
+            buffer.format.describe(),
+            buffer.indentation.describe(),
+            window.view.cursor_row + 1,
+            buffer.row_count()
+        );
+
+        let width = rect.width as usize;
+        let mut status: String = left.chars().take(width).collect();
+        let mut len = status.chars().count();
+        let right_len = right.chars().count();
+        while len < width {
+            if width - len == right_len {
+                status.push_str(&right);
+                break;
+            }
+            status.push(' ');
+            len += 1;
+        }
+
+        let style = Style {
+            reverse: true,
+            // With more than one window, the focused one stands out
+            bold: index == self.focused_window && self.windows.len() > 1,
+            ..Style::default()
+        };
+        let y = rect.y + rect.height - 1;
+        let end = frame.print(rect.x, y, rect.x + rect.width, &status, style);
+        frame.fill(end, y, rect.x + rect.width, style);
     }
 
-    fn refresh_screen(&mut self) -> crossterm::Result<()> {
-        self.scroll();
+    fn draw_separators(&self, frame: &mut Grid) {
+        for (x, y, height) in self.layout.separators(self.layout_area()) {
+            for row in y..y + height {
+                frame.put(x, row, "|", 1, Style::default());
+            }
+        }
+    }
+
+    fn draw_message_bar(&self, frame: &mut Grid) {
+        let y = match self.screen_rows.checked_sub(1) {
+            Some(y) => y,
+            None => return,
+        };
+        if self.status_message_time.elapsed() < STATUS_MESSAGE_TIMEOUT {
+            frame.print(
+                0,
+                y,
+                self.screen_cols,
+                &self.status_message,
+                Style::default(),
+            );
+        }
+    }
+
+    fn resize(&mut self, columns: u16, rows: u16) {
+        self.screen_cols = columns;
+        self.screen_rows = rows;
+        for window in &mut self.windows {
+            window.view.clamp(&self.buffers[window.buffer]);
+            // Scrolled as little as the new width needs, which after
+            // widening might be not at all
+            window.view.col_offset = 0;
+        }
+        // Whatever the terminal kept from before can't be trusted to be
+        // where the last frame was
+        self.screen.invalidate();
+    }
 
-        execute!(stdout(), Hide, MoveTo(0, 0))?;
+    fn set_status_message(&mut self, message: String) {
+        self.status_message = message;
+        self.status_message_time = Instant::now();
+    }
 
-        self.draw_rows()?;
+    fn refresh_screen(&mut self) -> crossterm::Result<()> {
+        let rects = self.layout.window_rects(self.layout_area());
+        for (index, rect) in &rects {
+            let window = &mut self.windows[*index];
+            let buffer = &mut self.buffers[window.buffer];
+            let text_rows = rect.height.saturating_sub(1);
+            window
+                .view
+                .scroll(buffer, text_rows as usize, rect.width as usize);
+            buffer.update_highlight(window.view.row_offset + text_rows as usize);
+        }
+
+        let mut frame = Grid::new(self.screen_cols, self.screen_rows);
+        for (index, rect) in &rects {
+            self.draw_window(&mut frame, *index, *rect);
+        }
+        self.draw_separators(&mut frame);
+        self.draw_message_bar(&mut frame);
 
-        execute!(stdout(), MoveTo(self.cursor_col - self.col_offset, self.cursor_row - self.row_offset), Show)?;
+        frame.cursor = match self.prompt_cursor {
+            // The message bar is the last row, below the windows
+            Some(prompt_cursor) => self
+                .screen_rows
+                .checked_sub(1)
+                .map(|y| (prompt_cursor.min(self.screen_cols.saturating_sub(1)), y)),
+            None => {
+                let view = &self.window().view;
+                let rect = rects
+                    .iter()
+                    .find(|(index, _)| *index == self.focused_window)
+                    .map_or(self.layout_area(), |(_, rect)| *rect);
+                // Only the distance from the offsets fits in a screen
+                // coordinate, the document coordinates don't have to. The
+                // cursor is hidden when it is outside the window, which is
+                // also the case in one too small to show any text.
+                let row = view.cursor_row.checked_sub(view.row_offset);
+                let col = view.render_col.checked_sub(view.col_offset);
+                match (row, col) {
+                    (Some(row), Some(col))
+                        if row < rect.height.saturating_sub(1) as usize
+                            && col < rect.width as usize =>
+                    {
+                        Some((rect.x + col as u16, rect.y + row as u16))
+                    }
+                    _ => None,
+                }
+            }
+        };
+        self.screen.draw(frame, &mut *self.terminal)?;
 
         Ok(())
     }
 }
 
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
-}
-
 fn event_loop(state: &mut EditorState) -> crossterm::Result<()> {
     loop {
         state.refresh_screen()?;
-        let event = read()?;
+        let event = state.terminal.read()?;
 
         match event {
-            Event::Resize(columns, rows) => {
-                // I have no idea why these plus 1s are need but they are
-                state.screen_cols = columns + 1;
-                state.screen_rows = rows + 1;
-            }
+            Event::Resize(columns, rows) => state.resize(columns, rows),
             Event::Key(key) => {
-                state.handle_keypress(key);
+                state.handle_keypress(key)?;
+                if state.should_quit {
+                    return Ok(());
+                }
             }
-            Event::Mouse(_) => {}
+            Event::Mouse(mouse) => state.handle_mouse(mouse)?,
         }
     }
 }
 
-fn setup() -> crossterm::Result<()> {
-    execute!(stdout(), EnterAlternateScreen)?;
-    enable_raw_mode()?;
-    Ok(())
+/// Restores the terminal before the default hook prints the panic message,
+/// otherwise it is lost on the alternate screen.
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = terminal::cleanup();
+        default_hook(info);
+    }));
+}
+
+const USAGE: &str = "Usage: kilors [OPTIONS] [FILE]...
+
+Opens each FILE in its own buffer, or an empty buffer if no FILE is given.
+A FILE that does not exist yet is created when it is saved.
+
+Options:
+    -h, --help       Print this help and exit
+    -V, --version    Print the version and exit";
+
+struct Args {
+    file_names: Vec<String>,
+}
+
+enum ParsedArgs {
+    Run(Args),
+    Help,
+    Version,
 }
 
-fn cleanup() -> crossterm::Result<()> {
-    disable_raw_mode()?;
-    execute!(stdout(), LeaveAlternateScreen)?;
-    Ok(())
+impl Args {
+    fn parse(args: impl Iterator<Item = String>) -> Result<ParsedArgs, String> {
+        let mut file_names = Vec::new();
+        let mut only_paths = false;
+
+        for arg in args {
+            if !only_paths && arg.starts_with('-') && arg.len() > 1 {
+                match arg.as_str() {
+                    "-h" | "--help" => return Ok(ParsedArgs::Help),
+                    "-V" | "--version" => return Ok(ParsedArgs::Version),
+                    "--" => only_paths = true,
+                    _ => return Err(format!("unknown option '{}'", arg)),
+                }
+            } else {
+                file_names.push(arg);
+            }
+        }
+
+        Ok(ParsedArgs::Run(Args { file_names }))
+    }
 }
 
-fn run() -> crossterm::Result<()> {
-    setup()?;
+fn run(args: Args) -> crossterm::Result<()> {
+    let mut state = EditorState::init(Box::new(CrosstermTerminal), SyntaxDatabase::load())?;
+    state.open_files(&args.file_names)?;
 
-    let mut state = EditorState::init()?;
-    state.load_file("./src/main.rs")?;
+    state.terminal.setup()?;
+
+    state.update_title()?;
+    let binary_file = state
+        .buffers
+        .iter()
+        .find(|buffer| buffer.format.binary)
+        .and_then(|buffer| buffer.file_name.as_deref());
+    let message = match (state.syntax_database.errors.first(), binary_file) {
+        (Some(error), _) => error.clone(),
+        (None, Some(file_name)) => format!(
+            "Warning: {} looks like a binary file, invalid bytes are shown as \\xNN",
+            file_name
+        ),
+        (None, None) => String::from(
+            "HELP: Ctrl-S = save | Ctrl-Q = quit | Ctrl-F = find | Ctrl-G = go to line | Ctrl-Z/Y = undo/redo | Ctrl-N/P/B = next/previous/list buffers | Ctrl-W = windows | Ctrl-E = convert format | Ctrl-T = indentation",
+        ),
+    };
+    state.set_status_message(message);
 
     event_loop(&mut state)?;
 
-    cleanup()
+    state.terminal.cleanup()
 }
 
 fn main() {
-    if let Err(e) = run() {
-        println!("Error: {:?}\r", e);
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(ParsedArgs::Run(args)) => args,
+        Ok(ParsedArgs::Help) => {
+            println!("{}", USAGE);
+            return;
+        }
+        Ok(ParsedArgs::Version) => {
+            println!("kilors {}", env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(message) => {
+            eprintln!("kilors: {}\n\n{}", message, USAGE);
+            exit(2);
+        }
+    };
+
+    install_panic_hook();
+
+    if let Err(e) = run(args) {
+        eprintln!("Error: {:?}", e);
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, fs, io, rc::Rc};
+
+    use crossterm::{
+        event::{MouseButton, MouseEventKind},
+        ErrorKind,
+    };
+
+    use super::*;
+    use terminal::{FakeScreen, FakeTerminal};
+
+    /// Runs the editor on `terminal` until it quits or runs out of events.
+    fn run_editor(terminal: FakeTerminal, file_names: &[String]) -> EditorState {
+        let mut state =
+            EditorState::init(Box::new(terminal), SyntaxDatabase::load_from(None)).unwrap();
+        state.open_files(file_names).unwrap();
+        match event_loop(&mut state) {
+            Ok(()) => {}
+            Err(ErrorKind::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => {}
+            Err(e) => panic!("editor failed: {:?}", e),
+        }
+        state
+    }
+
+    fn rows(screen: &Rc<RefCell<FakeScreen>>) -> Vec<String> {
+        let screen = screen.borrow();
+        (0..screen.size.1)
+            .map(|y| screen.backend.grid.row_text(y))
+            .collect()
+    }
+
+    #[test]
+    fn opens_edits_and_saves_a_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("notes.txt");
+        fs::write(&path, "hello\nworld\n").unwrap();
+
+        let (mut terminal, screen) = FakeTerminal::new(30, 6);
+        terminal
+            .type_text("abc ")
+            .key(KeyCode::Down, KeyModifiers::NONE)
+            .type_text("!")
+            .ctrl('s');
+        let state = run_editor(terminal, &[path.to_string_lossy().into_owned()]);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "abc hello\nworl!d\n");
+        assert!(!state.buffer().dirty);
+        let rows = rows(&screen);
+        assert_eq!(rows[0], format!("{:30}", "abc hello"));
+        assert_eq!(rows[1], format!("{:30}", "worl!d"));
+        assert_eq!(rows[2], format!("{:30}", "~"));
+        assert!(rows[4].ends_with(" - 2 lines"));
+        assert_eq!(rows[5], format!("{:30}", "Saved 17 bytes"));
+        assert_eq!(screen.borrow().backend.cursor(), Some((5, 1)));
+    }
+
+    #[test]
+    fn saves_a_new_buffer_under_a_prompted_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("new.txt");
+
+        let (mut terminal, screen) = FakeTerminal::new(40, 5);
+        terminal
+            .type_text("one")
+            .key(KeyCode::Enter, KeyModifiers::NONE)
+            .type_text("two")
+            .ctrl('s')
+            .type_text(&path.to_string_lossy())
+            .key(KeyCode::Enter, KeyModifiers::NONE);
+        run_editor(terminal, &[]);
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo\n");
+        assert_eq!(rows(&screen)[4], format!("{:40}", "Saved 8 bytes"));
+        assert_eq!(
+            screen.borrow().title,
+            format!("kilors - {}", path.to_string_lossy())
+        );
+    }
+
+    #[test]
+    fn quitting_with_unsaved_changes_needs_confirming() {
+        let (mut terminal, screen) = FakeTerminal::new(80, 4);
+        terminal.type_text("x").ctrl('q');
+        let state = run_editor(terminal, &[]);
+        assert!(!state.should_quit);
+        assert!(rows(&screen)[3].starts_with("WARNING!!! File has unsaved changes."));
+
+        let (mut terminal, _) = FakeTerminal::new(80, 4);
+        terminal.type_text("x").ctrl('q').ctrl('q').ctrl('q');
+        let state = run_editor(terminal, &[]);
+        assert!(state.should_quit);
+    }
+
+    #[test]
+    fn highlights_search_matches() {
+        let (mut terminal, screen) = FakeTerminal::new(20, 4);
+        terminal.type_text("one two one").ctrl('f').type_text("two");
+        run_editor(terminal, &[]);
+
+        let screen = screen.borrow();
+        let grid = &screen.backend.grid;
+        assert_eq!(grid.row_text(0), format!("{:20}", "one two one"));
+        assert!((4..7).all(|x| grid.cell(x, 0).style == Highlight::Match.style()));
+        assert_eq!(grid.cell(3, 0).style, Highlight::Normal.style());
+        assert_eq!(screen.backend.cursor(), Some((11, 3)));
+    }
+
+    #[test]
+    fn splits_windows_side_by_side() {
+        let (mut terminal, screen) = FakeTerminal::new(21, 4);
+        terminal.type_text("abc").ctrl('w').type_text("v");
+        run_editor(terminal, &[]);
+
+        let rows = rows(&screen);
+        assert_eq!(rows[0], "abc       |abc       ");
+        assert_eq!(rows[1], "~         |~         ");
+        assert!(rows[2].starts_with("[No Name] "));
+    }
+
+    /// Queues Ctrl-W followed by each char of `commands`.
+    fn window_commands(terminal: &mut FakeTerminal, commands: &str) {
+        for command in commands.chars() {
+            terminal.ctrl('w').type_text(&command.to_string());
+        }
+    }
+
+    /// The percent of every split in the layout, outermost first.
+    fn split_percents(layout: &Layout) -> Vec<u16> {
+        match layout {
+            Layout::Window(_) => Vec::new(),
+            Layout::Split {
+                percent,
+                first,
+                second,
+                ..
+            } => {
+                let mut percents = vec![*percent];
+                percents.extend(split_percents(first));
+                percents.extend(split_percents(second));
+                percents
+            }
+        }
+    }
+
+    fn two_files(dir: &tempfile::TempDir) -> Vec<String> {
+        ["one", "two"]
+            .iter()
+            .map(|name| {
+                let path = dir.path().join(name);
+                fs::write(&path, format!("{}\n", name)).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn closes_a_window_inside_a_nested_split() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut terminal, screen) = FakeTerminal::new(21, 8);
+        // One on the left, and on the right one above another showing the
+        // second file
+        window_commands(&mut terminal, "vs");
+        terminal.ctrl('n');
+        window_commands(&mut terminal, "kc");
+        let state = run_editor(terminal, &two_files(&dir));
+
+        assert_eq!(state.windows.len(), 2);
+        assert_eq!(state.focused_window, 1);
+        assert_eq!(state.windows[1].buffer, 1);
+        let rects = state.layout.window_rects(state.layout_area());
+        let expected = [
+            (
+                0,
+                Rect {
+                    x: 0,
+                    y: 0,
+                    width: 10,
+                    height: 7,
+                },
+            ),
+            (
+                1,
+                Rect {
+                    x: 11,
+                    y: 0,
+                    width: 10,
+                    height: 7,
+                },
+            ),
+        ];
+        assert!(rects == expected);
+
+        let rows = rows(&screen);
+        assert_eq!(rows[0], "one       |two       ");
+        assert_eq!(rows[5], "~         |~         ");
+        assert!(rows[6].starts_with("[1/2] "));
+        assert_eq!(&rows[6][10..17], "|[2/2] ");
+    }
+
+    #[test]
+    fn resizing_stops_at_the_limits() {
+        let (mut terminal, _) = FakeTerminal::new(21, 21);
+        // Growing the lower window shrinks the upper one
+        window_commands(&mut terminal, "s++++++++++++");
+        let state = run_editor(terminal, &[]);
+        assert_eq!(split_percents(&state.layout), [10]);
+        let rects = state.layout.window_rects(state.layout_area());
+        assert_eq!((rects[0].1.height, rects[1].1.height), (2, 18));
+
+        let (mut terminal, screen) = FakeTerminal::new(21, 21);
+        window_commands(&mut terminal, "s------------------<");
+        let state = run_editor(terminal, &[]);
+        assert_eq!(split_percents(&state.layout), [90]);
+        let rects = state.layout.window_rects(state.layout_area());
+        assert_eq!((rects[0].1.height, rects[1].1.height), (18, 2));
+        assert!(rows(&screen)[20].starts_with("No split to resize"));
+
+        // Inside the lower window, side by side
+        let (mut terminal, _) = FakeTerminal::new(21, 21);
+        window_commands(&mut terminal, "sv>>>>>>>>>>>");
+        let state = run_editor(terminal, &[]);
+        assert_eq!(split_percents(&state.layout), [50, 10]);
+        let rects = state.layout.window_rects(state.layout_area());
+        assert_eq!((rects[1].1.width, rects[2].1.width), (2, 18));
+
+        let (mut terminal, _) = FakeTerminal::new(21, 21);
+        window_commands(&mut terminal, "sv<<<<<<<<<<<<<<<<<<+");
+        let state = run_editor(terminal, &[]);
+        assert_eq!(split_percents(&state.layout), [45, 90]);
+    }
+
+    #[test]
+    fn moves_focus_across_a_nested_split() {
+        // One window on top, and two side by side below it
+        let focus_after = |commands: &str| {
+            let (mut terminal, _) = FakeTerminal::new(21, 10);
+            window_commands(&mut terminal, "sv");
+            window_commands(&mut terminal, commands);
+            run_editor(terminal, &[]).focused_window
+        };
+        assert_eq!(focus_after(""), 2);
+        assert_eq!(focus_after("k"), 0);
+        assert_eq!(focus_after("kj"), 1);
+        assert_eq!(focus_after("kjl"), 2);
+        assert_eq!(focus_after("kjlh"), 1);
+        assert_eq!(focus_after("hk"), 0);
+        // Nothing is there to move to
+        assert_eq!(focus_after("l"), 2);
+        assert_eq!(focus_after("kk"), 0);
+        assert_eq!(focus_after("jj"), 2);
+
+        // Going down lines up with the cursor
+        let (mut terminal, _) = FakeTerminal::new(21, 10);
+        window_commands(&mut terminal, "svk");
+        terminal.type_text("0123456789abcd");
+        window_commands(&mut terminal, "j");
+        assert_eq!(run_editor(terminal, &[]).focused_window, 2);
+    }
+
+    #[test]
+    fn keeps_only_the_focused_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut terminal, screen) = FakeTerminal::new(21, 8);
+        window_commands(&mut terminal, "vs");
+        terminal.ctrl('n').key(KeyCode::End, KeyModifiers::NONE);
+        window_commands(&mut terminal, "o");
+        let state = run_editor(terminal, &two_files(&dir));
+
+        assert_eq!(state.windows.len(), 1);
+        assert_eq!(state.focused_window, 0);
+        assert!(matches!(state.layout, Layout::Window(0)));
+        assert_eq!(state.windows[0].buffer, 1);
+        assert_eq!(state.windows[0].view.cursor_col, 3);
+
+        let rows = rows(&screen);
+        assert_eq!(rows[0], format!("{:21}", "two"));
+        assert!(rows[6].starts_with("[2/2] "));
+    }
+
+    #[test]
+    fn redraws_everything_at_each_new_size() {
+        let (mut terminal, screen) = FakeTerminal::new(30, 8);
+        terminal
+            .type_text("first")
+            .key(KeyCode::Enter, KeyModifiers::NONE);
+        terminal.type_text("second");
+        for &(columns, rows) in &[(12, 5), (3, 2), (40, 4)] {
+            terminal.push(Event::Resize(columns, rows));
+        }
+        run_editor(terminal, &[]);
+
+        // The fake starts from a blank screen on every resize, so anything
+        // left over would be missing. The one row of text at 3x2 scrolled
+        // down to the cursor, and stays there.
+        let rows = rows(&screen);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[0], format!("{:40}", "second"));
+        assert_eq!(rows[1], format!("{:40}", "~"));
+        assert!(rows[2].starts_with("[No Name] - 2 lines (modified)"));
+        assert_eq!(rows[3], " ".repeat(40));
+        assert_eq!(screen.borrow().backend.cursor(), Some((6, 0)));
+    }
+
+    #[test]
+    fn keeps_the_cursor_on_screen_when_shrinking() {
+        let (mut terminal, screen) = FakeTerminal::new(20, 12);
+        for row in 0..10 {
+            terminal
+                .type_text(&format!("row {}", row))
+                .key(KeyCode::Enter, KeyModifiers::NONE);
+        }
+        terminal
+            .key(KeyCode::Up, KeyModifiers::NONE)
+            .push(Event::Resize(4, 4));
+        let state = run_editor(terminal, &[]);
+
+        let view = &state.window().view;
+        assert_eq!((view.cursor_row, view.cursor_col), (9, 0));
+        assert_eq!((view.row_offset, view.col_offset), (8, 0));
+        let rows = rows(&screen);
+        assert_eq!(rows[..2], ["row ", "row "]);
+        assert_eq!(screen.borrow().backend.cursor(), Some((0, 1)));
+    }
+
+    #[test]
+    fn survives_tiny_terminals() {
+        for &(columns, rows) in &[(1, 1), (1, 2), (1, 3), (0, 5), (5, 0), (0, 0)] {
+            let (mut terminal, screen) = FakeTerminal::new(columns, rows);
+            terminal
+                .type_text("some text")
+                .key(KeyCode::Enter, KeyModifiers::NONE)
+                .type_text("漢字")
+                .ctrl('w')
+                .type_text("v")
+                .ctrl('w')
+                .type_text("s")
+                .push(Event::Resize(columns, rows));
+            let state = run_editor(terminal, &[]);
+
+            assert_eq!(state.buffer().row_count(), 2);
+            let screen = screen.borrow();
+            assert_eq!(screen.backend.grid.height, rows);
+            if let Some((x, y)) = screen.backend.cursor() {
+                assert!(
+                    x < columns && y < rows,
+                    "cursor outside {}x{}",
+                    columns,
+                    rows
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn uses_the_last_rows_for_the_status_and_message_bars() {
+        let (mut terminal, screen) = FakeTerminal::new(20, 2);
+        terminal.type_text("abc").push(Event::Resize(20, 1));
+        run_editor(terminal, &[]);
+
+        // One row only fits the message bar
+        assert_eq!(rows(&screen), [" ".repeat(20)]);
+        assert_eq!(screen.borrow().backend.cursor(), None);
+
+        let (mut terminal, screen) = FakeTerminal::new(20, 1);
+        terminal.type_text("abc").push(Event::Resize(20, 3));
+        run_editor(terminal, &[]);
+
+        let rows = rows(&screen);
+        assert_eq!(rows[0], format!("{:20}", "abc"));
+        assert!(rows[1].starts_with("[No Name] - 1 lines"));
+        assert_eq!(screen.borrow().backend.cursor(), Some((3, 0)));
+    }
+
+    fn mouse_test_file(dir: &tempfile::TempDir) -> String {
+        let path = dir.path().join("mouse.txt");
+        let text: String = (0..30).map(|row| format!("\trow {} here\n", row)).collect();
+        fs::write(&path, text).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn click_places_the_cursor_through_tabs_and_offsets() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut terminal, screen) = FakeTerminal::new(30, 8);
+        terminal
+            .key(KeyCode::PageDown, KeyModifiers::NONE)
+            .key(KeyCode::PageDown, KeyModifiers::NONE)
+            .mouse(MouseEventKind::Down(MouseButton::Left), 12, 2)
+            .mouse(MouseEventKind::Up(MouseButton::Left), 12, 2);
+        let state = run_editor(terminal, &[mouse_test_file(&dir)]);
+
+        // Two pages of six rows scroll down twelve rows, and the tab takes
+        // the first eight columns
+        let view = &state.window().view;
+        assert_eq!(view.row_offset, 12);
+        assert_eq!((view.cursor_row, view.cursor_col), (14, 5));
+        assert_eq!(view.selection(), None);
+        assert_eq!(screen.borrow().backend.cursor(), Some((12, 2)));
+    }
+
+    #[test]
+    fn click_inside_a_tab_goes_to_the_tab() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut terminal, _) = FakeTerminal::new(30, 8);
+        terminal.mouse(MouseEventKind::Down(MouseButton::Left), 5, 1);
+        let state = run_editor(terminal, &[mouse_test_file(&dir)]);
+
+        let view = &state.window().view;
+        assert_eq!((view.cursor_row, view.cursor_col), (1, 0));
+    }
+
+    #[test]
+    fn drag_selects_text() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut terminal, screen) = FakeTerminal::new(30, 8);
+        terminal
+            .mouse(MouseEventKind::Down(MouseButton::Left), 10, 1)
+            .mouse(MouseEventKind::Drag(MouseButton::Left), 12, 1)
+            .mouse(MouseEventKind::Drag(MouseButton::Left), 9, 2)
+            .mouse(MouseEventKind::Up(MouseButton::Left), 9, 2);
+        let state = run_editor(terminal, &[mouse_test_file(&dir)]);
+
+        assert_eq!(state.window().view.selection(), Some(((1, 3), (2, 2))));
+        let screen = screen.borrow();
+        let grid = &screen.backend.grid;
+        let selected = Highlight::Selection.style();
+        assert!((10..18).all(|x| grid.cell(x, 1).style == selected));
+        assert_eq!(grid.cell(9, 1).style, Highlight::Normal.style());
+        assert_eq!(grid.cell(8, 2).style, selected);
+        assert_eq!(grid.cell(9, 2).style, Highlight::Normal.style());
+
+        // Typing drops the selection
+        drop(screen);
+        let (mut terminal, _) = FakeTerminal::new(30, 8);
+        terminal
+            .mouse(MouseEventKind::Down(MouseButton::Left), 10, 1)
+            .mouse(MouseEventKind::Drag(MouseButton::Left), 12, 1)
+            .key(KeyCode::Right, KeyModifiers::NONE);
+        let state = run_editor(terminal, &[mouse_test_file(&dir)]);
+        assert_eq!(state.window().view.selection(), None);
+    }
+
+    #[test]
+    fn double_click_selects_a_word_and_triple_click_a_row() {
+        let dir = tempfile::tempdir().unwrap();
+        let click = MouseEventKind::Down(MouseButton::Left);
+        let (mut terminal, _) = FakeTerminal::new(30, 8);
+        terminal.mouse(click, 15, 3).mouse(click, 15, 3);
+        let state = run_editor(terminal, &[mouse_test_file(&dir)]);
+        assert_eq!(state.window().view.selection(), Some(((3, 7), (3, 11))));
+
+        let (mut terminal, _) = FakeTerminal::new(30, 8);
+        terminal
+            .mouse(click, 15, 3)
+            .mouse(click, 15, 3)
+            .mouse(click, 15, 3);
+        let state = run_editor(terminal, &[mouse_test_file(&dir)]);
+        assert_eq!(state.window().view.selection(), Some(((3, 0), (4, 0))));
+    }
+
+    #[test]
+    fn wheel_scrolls_without_moving_the_cursor() {
+        let dir = tempfile::tempdir().unwrap();
+        let (mut terminal, screen) = FakeTerminal::new(30, 8);
+        terminal
+            .mouse(MouseEventKind::ScrollDown, 3, 3)
+            .mouse(MouseEventKind::ScrollDown, 3, 3);
+        let state = run_editor(terminal, &[mouse_test_file(&dir)]);
+
+        let view = &state.window().view;
+        assert_eq!((view.cursor_row, view.cursor_col), (0, 0));
+        assert_eq!(view.row_offset, 6);
+        assert_eq!(rows(&screen)[0], format!("{:30}", "        row 6 here"));
+        // The cursor is scrolled off the top
+        assert_eq!(screen.borrow().backend.cursor(), None);
+
+        // Moving the cursor brings it back into view
+        let (mut terminal, screen) = FakeTerminal::new(30, 8);
+        terminal
+            .mouse(MouseEventKind::ScrollDown, 3, 3)
+            .mouse(MouseEventKind::ScrollUp, 3, 3)
+            .mouse(MouseEventKind::ScrollDown, 3, 3)
+            .key(KeyCode::Down, KeyModifiers::NONE);
+        let state = run_editor(terminal, &[mouse_test_file(&dir)]);
+        assert_eq!(state.window().view.row_offset, 1);
+        assert_eq!(screen.borrow().backend.cursor(), Some((0, 0)));
+    }
+
+    #[test]
+    fn click_focuses_the_window_under_it() {
+        let (mut terminal, _) = FakeTerminal::new(21, 6);
+        terminal.type_text("abc").ctrl('w').type_text("v").mouse(
+            MouseEventKind::Down(MouseButton::Left),
+            13,
+            0,
+        );
+        let state = run_editor(terminal, &[]);
+
+        assert_eq!(state.focused_window, 1);
+        let view = &state.window().view;
+        assert_eq!((view.cursor_row, view.cursor_col), (0, 2));
     }
 }
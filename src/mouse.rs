@@ -0,0 +1,155 @@
+use std::time::{Duration, Instant};
+
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+
+use crate::{window::Rect, EditorState};
+
+/// Clicks at the same place closer together than this make a double or
+/// triple click.
+const MULTI_CLICK_TIME: Duration = Duration::from_millis(400);
+/// How many rows a step of the mouse wheel scrolls.
+const WHEEL_SCROLL_ROWS: usize = 3;
+
+/// The last click, to tell double and triple clicks from single ones.
+pub(crate) struct Click {
+    time: Instant,
+    position: (u16, u16),
+    /// 1 to 3, going back to 1 after a triple click.
+    count: u8,
+}
+
+impl EditorState {
+    pub(crate) fn handle_mouse(&mut self, event: MouseEvent) -> crossterm::Result<()> {
+        let position = (event.column, event.row);
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => self.click(position)?,
+            MouseEventKind::Drag(MouseButton::Left) => self.drag(position),
+            MouseEventKind::ScrollUp => self.scroll_wheel(position, false),
+            MouseEventKind::ScrollDown => self.scroll_wheel(position, true),
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// The window at a screen position, and its area.
+    fn window_at(&self, (x, y): (u16, u16)) -> Option<(usize, Rect)> {
+        self.layout
+            .window_rects(self.layout_area())
+            .into_iter()
+            .find(|(_, rect)| rect.contains(x, y))
+    }
+
+    /// The buffer position drawn at a screen position in a window, going by
+    /// its offsets and where tabs and wide chars put each char. Positions
+    /// above or left of the window go to the rows and columns scrolled off
+    /// that way, and anything below the text to the end of the last row.
+    fn buffer_position(&self, window: usize, rect: Rect, (x, y): (u16, u16)) -> (usize, usize) {
+        let window = &self.windows[window];
+        let buffer = &self.buffers[window.buffer];
+        let view = &window.view;
+        let row = (view.row_offset + y as usize).saturating_sub(rect.y as usize);
+        let render_col = (view.col_offset + x as usize).saturating_sub(rect.x as usize);
+
+        match buffer.row(row) {
+            Some(editor_row) => (row, editor_row.char_index(render_col)),
+            None => {
+                let last_row = buffer.row_count().saturating_sub(1);
+                (last_row, buffer.row(last_row).map_or(0, |row| row.len()))
+            }
+        }
+    }
+
+    fn click(&mut self, position: (u16, u16)) -> crossterm::Result<()> {
+        let (index, rect) = match self.window_at(position) {
+            Some(window) => window,
+            None => return Ok(()),
+        };
+        let count = match &self.last_click {
+            Some(click)
+                if click.position == position && click.time.elapsed() < MULTI_CLICK_TIME =>
+            {
+                click.count % 3 + 1
+            }
+            _ => 1,
+        };
+        self.last_click = Some(Click {
+            time: Instant::now(),
+            position,
+            count,
+        });
+
+        if index != self.focused_window {
+            self.focused_window = index;
+            self.update_title()?;
+        }
+        // The status bar only focuses its window
+        if position.1 == rect.y + rect.height - 1 {
+            return Ok(());
+        }
+
+        let (row, col) = self.buffer_position(index, rect, position);
+        let (buffer, view) = self.buffer_and_view();
+        buffer.undo_history.seal();
+        view.free_scroll = false;
+        view.preferred_render_col = None;
+        let (anchor, cursor) = match count {
+            1 => ((row, col), (row, col)),
+            2 => {
+                let (start, end) = buffer.word_at(row, col);
+                ((row, start), (row, end))
+            }
+            // The whole row, along with its line ending if another row
+            // follows
+            _ if row + 1 < buffer.row_count() => ((row, 0), (row + 1, 0)),
+            _ => ((row, 0), (row, buffer.row(row).map_or(0, |row| row.len()))),
+        };
+        view.anchor = Some(anchor);
+        view.set_cursor(cursor);
+
+        Ok(())
+    }
+
+    /// Moves the cursor with the mouse while the anchor of the click stays
+    /// put. The drag stays in the window it started in, and going past its
+    /// edges scrolls it.
+    fn drag(&mut self, position: (u16, u16)) {
+        let index = self.focused_window;
+        let rect = match self
+            .layout
+            .window_rects(self.layout_area())
+            .into_iter()
+            .find(|(window, _)| *window == index)
+        {
+            Some((_, rect)) => rect,
+            None => return,
+        };
+
+        let cursor = self.buffer_position(index, rect, position);
+        let (buffer, view) = self.buffer_and_view();
+        buffer.undo_history.seal();
+        view.free_scroll = false;
+        view.preferred_render_col = None;
+        if view.anchor.is_none() {
+            view.anchor = Some((view.cursor_row, view.cursor_col));
+        }
+        view.set_cursor(cursor);
+    }
+
+    /// Scrolls the window under the mouse, leaving its cursor where it is.
+    fn scroll_wheel(&mut self, position: (u16, u16), down: bool) {
+        let index = match self.window_at(position) {
+            Some((index, _)) => index,
+            None => return,
+        };
+        let window = &mut self.windows[index];
+        let last_row = self.buffers[window.buffer].row_count().saturating_sub(1);
+        let view = &mut window.view;
+        view.row_offset = if down {
+            (view.row_offset + WHEEL_SCROLL_ROWS).min(last_row)
+        } else {
+            view.row_offset.saturating_sub(WHEEL_SCROLL_ROWS)
+        };
+        view.free_scroll = true;
+    }
+}
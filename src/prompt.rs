@@ -0,0 +1,335 @@
+use std::collections::HashMap;
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+use unicode_width::UnicodeWidthStr;
+
+use crate::EditorState;
+
+const HISTORY_LENGTH: usize = 100;
+
+/// What happened to a prompt, passed to its callback.
+pub(crate) enum PromptEvent {
+    /// A key was pressed. The callback returns `true` if it handled the key
+    /// itself, in which case the prompt ignores it.
+    Key(KeyEvent),
+    /// The input text changed.
+    Edited,
+    Submitted,
+    Cancelled,
+}
+
+pub(crate) type PromptCallback = fn(&mut EditorState, &str, PromptEvent) -> bool;
+
+/// Previous inputs for each prompt, most recent last.
+#[derive(Default)]
+pub(crate) struct PromptHistory {
+    entries: HashMap<String, Vec<String>>,
+}
+
+impl PromptHistory {
+    fn get(&self, prompt: &str) -> &[String] {
+        self.entries.get(prompt).map_or(&[], |entries| entries)
+    }
+
+    fn push(&mut self, prompt: &str, input: &str) {
+        let entries = self.entries.entry(prompt.to_string()).or_default();
+        if entries.last().map(String::as_str) != Some(input) {
+            entries.push(input.to_string());
+        }
+        if entries.len() > HISTORY_LENGTH {
+            entries.remove(0);
+        }
+    }
+}
+
+/// The line being edited in a prompt, with the cursor as a char index.
+struct PromptInput {
+    text: String,
+    cursor: usize,
+}
+
+impl PromptInput {
+    fn byte_index(&self, at: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(at)
+            .map_or(self.text.len(), |(index, _)| index)
+    }
+
+    fn len(&self) -> usize {
+        self.text.chars().count()
+    }
+
+    fn set(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.len();
+    }
+
+    fn insert(&mut self, char: char) {
+        let index = self.byte_index(self.cursor);
+        self.text.insert(index, char);
+        self.cursor += 1;
+    }
+
+    fn delete(&mut self, at: usize) {
+        if at < self.len() {
+            let index = self.byte_index(at);
+            self.text.remove(index);
+        }
+    }
+}
+
+impl EditorState {
+    /// Reads a line of input in the message bar. `prompt` is shown with `{}`
+    /// replaced by the input so far. Returns `None` if the prompt was
+    /// cancelled with Esc.
+    pub(crate) fn prompt(
+        &mut self,
+        prompt: &str,
+        callback: Option<PromptCallback>,
+    ) -> crossterm::Result<Option<String>> {
+        let mut input = PromptInput {
+            text: String::new(),
+            cursor: 0,
+        };
+        let prefix_width = prompt.split("{}").next().unwrap_or("").width();
+        let history_len = self.prompt_history.get(prompt).len();
+        let mut history_index = history_len;
+        let mut draft = String::new();
+
+        let result = loop {
+            self.set_status_message(prompt.replacen("{}", &input.text, 1));
+            let before_cursor: String = input.text.chars().take(input.cursor).collect();
+            self.prompt_cursor = Some((prefix_width + before_cursor.width()) as u16);
+            self.refresh_screen()?;
+
+            let key = match self.terminal.read()? {
+                Event::Key(key) => key,
+                Event::Resize(columns, rows) => {
+                    self.resize(columns, rows);
+                    continue;
+                }
+                Event::Mouse(_) => continue,
+            };
+
+            match key.code {
+                KeyCode::Esc => {
+                    if let Some(callback) = callback {
+                        callback(self, &input.text, PromptEvent::Cancelled);
+                    }
+                    break None;
+                }
+                KeyCode::Enter if !input.text.is_empty() => {
+                    if let Some(callback) = callback {
+                        callback(self, &input.text, PromptEvent::Submitted);
+                    }
+                    self.prompt_history.push(prompt, &input.text);
+                    break Some(input.text);
+                }
+                _ => {}
+            }
+
+            if let Some(callback) = callback {
+                if callback(self, &input.text, PromptEvent::Key(key)) {
+                    continue;
+                }
+            }
+
+            let old_text = input.text.clone();
+            match key.code {
+                KeyCode::Left => input.cursor = input.cursor.saturating_sub(1),
+                KeyCode::Right => input.cursor = (input.cursor + 1).min(input.len()),
+                KeyCode::Home => input.cursor = 0,
+                KeyCode::End => input.cursor = input.len(),
+                KeyCode::Backspace if input.cursor > 0 => {
+                    input.cursor -= 1;
+                    input.delete(input.cursor);
+                }
+                KeyCode::Delete => input.delete(input.cursor),
+                KeyCode::Up if history_index > 0 => {
+                    if history_index == history_len {
+                        draft = input.text.clone();
+                    }
+                    history_index -= 1;
+                    input.set(&self.prompt_history.get(prompt)[history_index]);
+                }
+                KeyCode::Down if history_index < history_len => {
+                    history_index += 1;
+                    if history_index == history_len {
+                        input.set(&draft);
+                    } else {
+                        input.set(&self.prompt_history.get(prompt)[history_index]);
+                    }
+                }
+                KeyCode::Char(char)
+                    if !key
+                        .modifiers
+                        .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+                {
+                    input.insert(char)
+                }
+                _ => {}
+            }
+
+            if input.text != old_text {
+                if let Some(callback) = callback {
+                    callback(self, &input.text, PromptEvent::Edited);
+                }
+            }
+        };
+
+        self.prompt_cursor = None;
+        self.set_status_message(String::new());
+
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{syntax::SyntaxDatabase, terminal::FakeTerminal};
+
+    fn editor(terminal: FakeTerminal) -> EditorState {
+        EditorState::init(Box::new(terminal), SyntaxDatabase::load_from(None)).unwrap()
+    }
+
+    fn enter(terminal: &mut FakeTerminal) {
+        terminal.key(KeyCode::Enter, KeyModifiers::NONE);
+    }
+
+    fn keys(terminal: &mut FakeTerminal, code: KeyCode, count: usize) {
+        for _ in 0..count {
+            terminal.key(code, KeyModifiers::NONE);
+        }
+    }
+
+    #[test]
+    fn goes_through_the_history() {
+        let (mut terminal, _) = FakeTerminal::new(40, 5);
+        terminal.type_text("one");
+        enter(&mut terminal);
+        terminal.type_text("two");
+        enter(&mut terminal);
+        // Back past the oldest entry, then forward again
+        terminal.type_text("dra");
+        keys(&mut terminal, KeyCode::Up, 3);
+        keys(&mut terminal, KeyCode::Down, 1);
+        enter(&mut terminal);
+        // Down past the newest entry brings back what was being typed
+        terminal.type_text("draft");
+        keys(&mut terminal, KeyCode::Up, 1);
+        keys(&mut terminal, KeyCode::Down, 2);
+        enter(&mut terminal);
+        keys(&mut terminal, KeyCode::Up, 3);
+        enter(&mut terminal);
+        // Another prompt has a history of its own
+        keys(&mut terminal, KeyCode::Up, 1);
+        enter(&mut terminal);
+        terminal.key(KeyCode::Esc, KeyModifiers::NONE);
+
+        let mut state = editor(terminal);
+        let mut prompt = |prompt| state.prompt(prompt, None).unwrap();
+        assert_eq!(prompt("Input: {}").as_deref(), Some("one"));
+        assert_eq!(prompt("Input: {}").as_deref(), Some("two"));
+        assert_eq!(prompt("Input: {}").as_deref(), Some("two"));
+        assert_eq!(prompt("Input: {}").as_deref(), Some("draft"));
+        assert_eq!(prompt("Input: {}").as_deref(), Some("one"));
+        // Enter does nothing while the input is empty
+        assert_eq!(prompt("Other: {}"), None);
+        assert_eq!(
+            state.prompt_history.get("Input: {}"),
+            ["one", "two", "draft", "one"]
+        );
+    }
+
+    #[test]
+    fn edits_in_the_middle_of_the_line() {
+        let (mut terminal, _) = FakeTerminal::new(40, 5);
+        terminal.type_text("héllo wörld");
+        keys(&mut terminal, KeyCode::Left, 5);
+        keys(&mut terminal, KeyCode::Backspace, 1);
+        keys(&mut terminal, KeyCode::Delete, 1);
+        terminal.type_text("ß");
+        keys(&mut terminal, KeyCode::Home, 1);
+        keys(&mut terminal, KeyCode::Delete, 1);
+        // Backspace at the start does nothing
+        keys(&mut terminal, KeyCode::Backspace, 1);
+        keys(&mut terminal, KeyCode::End, 1);
+        keys(&mut terminal, KeyCode::Right, 1);
+        keys(&mut terminal, KeyCode::Backspace, 1);
+        keys(&mut terminal, KeyCode::Delete, 1);
+        enter(&mut terminal);
+
+        let mut state = editor(terminal);
+        let input = state.prompt("Input: {}", None).unwrap();
+        assert_eq!(input.as_deref(), Some("élloßörl"));
+    }
+
+    #[test]
+    fn puts_the_cursor_after_wide_chars() {
+        let (mut terminal, screen) = FakeTerminal::new(40, 5);
+        terminal.type_text("漢字x");
+        keys(&mut terminal, KeyCode::Left, 2);
+        terminal.key(KeyCode::Esc, KeyModifiers::NONE);
+
+        let mut state = editor(terminal);
+        state.prompt("Find: {} (Esc)", None).unwrap();
+        // The last frame drawn is the one before Esc
+        let screen = screen.borrow();
+        assert!(screen.backend.grid.row_text(4).starts_with("Find: 漢"));
+        assert_eq!(screen.backend.cursor(), Some((8, 4)));
+    }
+
+    thread_local! {
+        static EVENTS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(_: &mut EditorState, input: &str, event: PromptEvent) -> bool {
+        let event = match event {
+            PromptEvent::Key(key) => format!("key {:?}", key.code),
+            PromptEvent::Edited => format!("edited {}", input),
+            PromptEvent::Submitted => format!("submitted {}", input),
+            PromptEvent::Cancelled => format!("cancelled {}", input),
+        };
+        EVENTS.with(|events| events.borrow_mut().push(event));
+        false
+    }
+
+    #[test]
+    fn esc_cancels() {
+        let (mut terminal, _) = FakeTerminal::new(40, 5);
+        terminal.type_text("ab");
+        keys(&mut terminal, KeyCode::Left, 1);
+        terminal.key(KeyCode::Esc, KeyModifiers::NONE);
+        terminal.type_text("c");
+        enter(&mut terminal);
+
+        let mut state = editor(terminal);
+        assert_eq!(state.prompt("Input: {}", Some(record)).unwrap(), None);
+        assert!(state.prompt_history.get("Input: {}").is_empty());
+        assert_eq!(state.prompt_cursor, None);
+        assert_eq!(
+            EVENTS.with(|events| events.take()),
+            [
+                "key Char('a')",
+                "edited a",
+                "key Char('b')",
+                "edited ab",
+                "key Left",
+                "cancelled ab",
+            ]
+        );
+
+        assert_eq!(
+            state.prompt("Input: {}", Some(record)).unwrap().as_deref(),
+            Some("c")
+        );
+        assert_eq!(
+            EVENTS.with(|events| events.take()),
+            ["key Char('c')", "edited c", "submitted c"]
+        );
+    }
+}
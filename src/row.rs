@@ -0,0 +1,249 @@
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{format::raw_byte, syntax::Highlight};
+
+/// A row as it is drawn on screen. Rows are only rendered while they are
+/// being drawn or the cursor is on them, never for the whole buffer.
+pub(crate) struct EditorRow {
+    pub(crate) text_raw: String,
+    pub(crate) text_render: String,
+    // Where in text_render each screen column starts, plus one trailing
+    // entry for the end. A grapheme wider than one column is all in its
+    // first column, and the columns after it are left empty
+    cells: Vec<usize>,
+    // The column in text_render where the grapheme of each char of text_raw
+    // starts, plus one trailing entry for the end of the line
+    pub(crate) render_cols: Vec<usize>,
+    pub(crate) highlight: Vec<Highlight>,
+    tab_width: usize,
+}
+
+impl EditorRow {
+    pub(crate) fn from(str: String, tab_width: usize) -> Self {
+        let mut row = Self {
+            text_raw: str,
+            text_render: String::new(),
+            cells: Vec::new(),
+            render_cols: Vec::new(),
+            highlight: Vec::new(),
+            tab_width,
+        };
+        row.update();
+        row
+    }
+
+    pub(crate) fn update(&mut self) {
+        self.text_render = String::new();
+        self.cells = vec![0];
+        self.render_cols = Vec::new();
+        let text = std::mem::take(&mut self.text_raw);
+        // Every ASCII char is a grapheme of its own, and most rows are ASCII
+        let graphemes: Box<dyn Iterator<Item = &str>> = if text.is_ascii() {
+            Box::new((0..text.len()).map(|index| &text[index..index + 1]))
+        } else {
+            Box::new(text.graphemes(true))
+        };
+        for grapheme in graphemes {
+            // Tabs and escaped chars are drawn on their own, even when they
+            // are part of a bigger grapheme
+            if !grapheme
+                .chars()
+                .any(|char| char == '\t' || is_escaped(char))
+            {
+                let start = self.render_len();
+                self.render_cols.extend(grapheme.chars().map(|_| start));
+                self.push_grapheme(grapheme);
+                continue;
+            }
+
+            for char in grapheme.chars() {
+                self.render_cols.push(self.render_len());
+                match char {
+                    '\t' => {
+                        // A tab reaches the next tab stop
+                        let tab_width = self.tab_width - self.render_len() % self.tab_width;
+                        for _ in 0..tab_width {
+                            self.push_cell(" ", 1);
+                        }
+                    }
+                    char => match escape(char) {
+                        Some(escaped) => {
+                            for char in escaped.chars() {
+                                self.push_cell(char.encode_utf8(&mut [0; 4]), 1);
+                            }
+                        }
+                        None => self.push_grapheme(char.encode_utf8(&mut [0; 4])),
+                    },
+                }
+            }
+        }
+        self.render_cols.push(self.render_len());
+        self.text_raw = text;
+    }
+
+    fn push_grapheme(&mut self, grapheme: &str) {
+        let width = grapheme.width();
+        if width == 0 {
+            // Something like a combining mark on its own still needs a
+            // column, or the cursor could never be put on it
+            self.push_cell(" ", 1);
+            self.push_cell(grapheme, 0);
+            return;
+        }
+        self.push_cell(grapheme, width);
+    }
+
+    fn push_cell(&mut self, text: &str, width: usize) {
+        self.text_render.push_str(text);
+        match width {
+            // Joins the text onto the cell before it
+            0 => *self.cells.last_mut().unwrap() = self.text_render.len(),
+            width => {
+                for _ in 0..width {
+                    self.cells.push(self.text_render.len());
+                }
+            }
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.render_cols.len() - 1
+    }
+
+    /// How many screen columns the row takes.
+    pub(crate) fn render_len(&self) -> usize {
+        self.cells.len() - 1
+    }
+
+    /// The text drawn in a column, which is empty if a wide grapheme before
+    /// it covers it.
+    pub(crate) fn cell(&self, render_col: usize) -> &str {
+        &self.text_render[self.cells[render_col]..self.cells[render_col + 1]]
+    }
+
+    /// How many columns the grapheme starting at `render_col` takes.
+    pub(crate) fn cell_width(&self, render_col: usize) -> usize {
+        1 + (render_col + 1..self.render_len())
+            .take_while(|&col| self.cell(col).is_empty())
+            .count()
+    }
+
+    pub(crate) fn render_col(&self, at: usize) -> usize {
+        self.render_cols[at.min(self.len())]
+    }
+
+    /// The first char of the grapheme whose render covers `render_col`, or
+    /// the end of the line if it is past the last grapheme.
+    pub(crate) fn char_index(&self, render_col: usize) -> usize {
+        let last = self.render_cols.partition_point(|&col| col <= render_col) - 1;
+        let start_col = self.render_cols[last];
+        self.render_cols.partition_point(|&col| col < start_col)
+    }
+
+    /// Where the grapheme after the one at `at` starts.
+    pub(crate) fn next_grapheme(&self, at: usize) -> usize {
+        let start_col = self.render_col(at);
+        self.render_cols
+            .partition_point(|&col| col <= start_col)
+            .min(self.len())
+    }
+
+    /// Where the grapheme before the one at `at` starts.
+    pub(crate) fn previous_grapheme(&self, at: usize) -> usize {
+        match self.render_col(at).checked_sub(1) {
+            Some(render_col) => self.char_index(render_col),
+            None => 0,
+        }
+    }
+}
+
+/// Whether a char is drawn as an escape instead of being sent to the
+/// terminal as it is.
+pub(crate) fn is_escaped(char: char) -> bool {
+    (char.is_control() && char != '\t') || raw_byte(char).is_some()
+}
+
+/// What an escaped char is drawn as: caret notation such as "^A" for ASCII
+/// control chars and "\xNN" for bytes that aren't valid UTF-8.
+fn escape(char: char) -> Option<String> {
+    if !is_escaped(char) {
+        return None;
+    }
+    Some(match raw_byte(char) {
+        Some(byte) => format!("\\x{:02X}", byte),
+        None if char.is_ascii() => format!("^{}", (char as u8 ^ 0x40) as char),
+        None => format!("\\u{{{:x}}}", char as u32),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cells(row: &EditorRow) -> Vec<&str> {
+        (0..row.render_len()).map(|col| row.cell(col)).collect()
+    }
+
+    #[test]
+    fn zero_width_graphemes_get_a_column_of_their_own() {
+        let row = EditorRow::from(String::from("a\u{200b}b"), 8);
+        assert_eq!(cells(&row), ["a", " \u{200b}", "b"]);
+        assert_eq!(row.render_cols, [0, 1, 2, 3]);
+
+        // Even at the start of the row
+        let row = EditorRow::from(String::from("\u{301}x"), 8);
+        assert_eq!(cells(&row), [" \u{301}", "x"]);
+        assert_eq!(row.char_index(0), 0);
+        assert_eq!(row.char_index(1), 1);
+    }
+
+    #[test]
+    fn wide_graphemes_cover_the_columns_after_them() {
+        let row = EditorRow::from(String::from("a漢b"), 8);
+        assert_eq!(cells(&row), ["a", "漢", "", "b"]);
+        assert_eq!(row.cell_width(1), 2);
+        assert_eq!(row.cell_width(3), 1);
+        assert_eq!(row.render_col(2), 3);
+        assert_eq!(row.char_index(1), 1);
+        assert_eq!(row.char_index(2), 1);
+        assert_eq!(row.char_index(3), 2);
+        assert_eq!(row.char_index(10), 3);
+    }
+
+    #[test]
+    fn moves_by_whole_graphemes() {
+        // "e" and a combining acute accent make one grapheme
+        let row = EditorRow::from(String::from("e\u{301}漢x"), 8);
+        assert_eq!(row.len(), 4);
+        assert_eq!(row.render_cols, [0, 0, 1, 3, 4]);
+        assert_eq!(row.next_grapheme(0), 2);
+        assert_eq!(row.next_grapheme(2), 3);
+        assert_eq!(row.next_grapheme(3), 4);
+        assert_eq!(row.next_grapheme(4), 4);
+        assert_eq!(row.previous_grapheme(4), 3);
+        assert_eq!(row.previous_grapheme(3), 2);
+        assert_eq!(row.previous_grapheme(2), 0);
+        assert_eq!(row.previous_grapheme(0), 0);
+        assert_eq!(row.char_index(0), 0);
+    }
+
+    #[test]
+    fn escapes_control_chars_and_raw_bytes() {
+        let raw_ff = char::from_u32(0x10ffff).unwrap();
+        let row = EditorRow::from(format!("a\x01b\x7f{}c", raw_ff), 8);
+        assert_eq!(row.text_render, "a^Ab^?\\xFFc");
+        assert_eq!(row.render_cols, [0, 1, 3, 4, 6, 10, 11]);
+        assert_eq!(row.char_index(2), 1);
+        assert_eq!(row.char_index(8), 4);
+    }
+
+    #[test]
+    fn tabs_reach_the_next_tab_stop() {
+        let row = EditorRow::from(String::from("a\tb"), 4);
+        assert_eq!(row.text_render, "a   b");
+        assert_eq!(row.render_col(2), 4);
+        assert_eq!(row.char_index(2), 1);
+        assert_eq!(row.char_index(4), 2);
+    }
+}
@@ -0,0 +1,450 @@
+use std::io::Write;
+
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    queue,
+    style::{Attribute, Color, SetAttribute, SetForegroundColor},
+    terminal::{Clear, ClearType},
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct Style {
+    pub(crate) foreground: Color,
+    pub(crate) reverse: bool,
+    pub(crate) bold: bool,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            foreground: Color::Reset,
+            reverse: false,
+            bold: false,
+        }
+    }
+}
+
+/// One column of the screen. A grapheme wider than one column is in its
+/// first cell, and the cells it covers after that have no text.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Cell {
+    pub(crate) text: String,
+    pub(crate) style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            text: String::from(" "),
+            style: Style::default(),
+        }
+    }
+}
+
+/// A frame of the whole terminal, drawn in memory.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Grid {
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+    cells: Vec<Cell>,
+    /// Where the terminal cursor goes, or `None` to hide it.
+    pub(crate) cursor: Option<(u16, u16)>,
+}
+
+impl Grid {
+    pub(crate) fn new(width: u16, height: u16) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); width as usize * height as usize],
+            cursor: None,
+        }
+    }
+
+    pub(crate) fn cell(&self, x: u16, y: u16) -> &Cell {
+        &self.cells[y as usize * self.width as usize + x as usize]
+    }
+
+    fn cell_mut(&mut self, x: u16, y: u16) -> &mut Cell {
+        &mut self.cells[y as usize * self.width as usize + x as usize]
+    }
+
+    /// The text of a row, with the empty cells after wide graphemes left
+    /// out so it reads the way it looks.
+    #[cfg(test)]
+    pub(crate) fn row_text(&self, y: u16) -> String {
+        (0..self.width)
+            .map(|x| self.cell(x, y).text.as_str())
+            .collect()
+    }
+
+    /// Puts a grapheme `width` columns wide at (x, y). One that doesn't fit
+    /// before the right edge is replaced by spaces.
+    pub(crate) fn put(&mut self, x: u16, y: u16, grapheme: &str, width: usize, style: Style) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        if x as usize + width > self.width as usize {
+            for x in x..self.width {
+                self.put(x, y, " ", 1, style);
+            }
+            return;
+        }
+
+        *self.cell_mut(x, y) = Cell {
+            text: grapheme.to_string(),
+            style,
+        };
+        for covered in x + 1..x + width as u16 {
+            *self.cell_mut(covered, y) = Cell {
+                text: String::new(),
+                style,
+            };
+        }
+    }
+
+    /// Writes `text` from (x, y) onwards, cut off at `end`. Returns the
+    /// column after the last grapheme written.
+    pub(crate) fn print(&mut self, x: u16, y: u16, end: u16, text: &str, style: Style) -> u16 {
+        let end = end.min(self.width);
+        let mut x = x;
+        for grapheme in text.graphemes(true) {
+            let width = grapheme.width().max(1);
+            if x as usize + width > end as usize {
+                break;
+            }
+            self.put(x, y, grapheme, width, style);
+            x += width as u16;
+        }
+        x
+    }
+
+    /// Fills the columns from `x` up to `end` with spaces.
+    pub(crate) fn fill(&mut self, x: u16, y: u16, end: u16, style: Style) {
+        for x in x..end.min(self.width) {
+            self.put(x, y, " ", 1, style);
+        }
+    }
+}
+
+/// Draws frames onto the terminal. Each frame is compared with the one
+/// before it, and only the cells that changed are written out, all in a
+/// single write.
+#[derive(Default)]
+pub(crate) struct Screen {
+    // None when the terminal has to be redrawn from scratch
+    previous: Option<Grid>,
+}
+
+impl Screen {
+    /// Makes the next frame redraw every cell.
+    pub(crate) fn invalidate(&mut self) {
+        self.previous = None;
+    }
+
+    /// Writes the changes from the last frame to `frame` to `out` and
+    /// flushes it. A frame of a different size is redrawn from scratch.
+    pub(crate) fn draw(
+        &mut self,
+        frame: Grid,
+        out: &mut (impl Write + ?Sized),
+    ) -> crossterm::Result<()> {
+        let previous = self
+            .previous
+            .take()
+            .filter(|previous| (previous.width, previous.height) == (frame.width, frame.height));
+
+        let mut buffer = Vec::new();
+        queue!(buffer, Hide)?;
+        if previous.is_none() {
+            queue!(
+                buffer,
+                SetAttribute(Attribute::Reset),
+                Clear(ClearType::All)
+            )?;
+        }
+
+        let mut position = None;
+        let mut current_style = None;
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                let cell = frame.cell(x, y);
+                let unchanged = previous
+                    .as_ref()
+                    .is_some_and(|previous| previous.cell(x, y) == cell);
+                // The grapheme before an empty cell already drew over it
+                if unchanged || cell.text.is_empty() {
+                    continue;
+                }
+
+                if position != Some((x, y)) {
+                    queue!(buffer, MoveTo(x, y))?;
+                }
+                if current_style != Some(cell.style) {
+                    queue!(
+                        buffer,
+                        SetAttribute(Attribute::Reset),
+                        SetForegroundColor(cell.style.foreground)
+                    )?;
+                    if cell.style.reverse {
+                        queue!(buffer, SetAttribute(Attribute::Reverse))?;
+                    }
+                    if cell.style.bold {
+                        queue!(buffer, SetAttribute(Attribute::Bold))?;
+                    }
+                    current_style = Some(cell.style);
+                }
+                buffer.extend_from_slice(cell.text.as_bytes());
+                let width = 1
+                    + (x + 1..frame.width)
+                        .take_while(|&x| frame.cell(x, y).text.is_empty())
+                        .count() as u16;
+                position = Some((x + width, y));
+            }
+        }
+
+        if current_style.is_some() {
+            queue!(buffer, SetAttribute(Attribute::Reset))?;
+        }
+        if let Some((x, y)) = frame
+            .cursor
+            .filter(|&(x, y)| x < frame.width && y < frame.height)
+        {
+            queue!(buffer, MoveTo(x, y), Show)?;
+        }
+
+        out.write_all(&buffer)?;
+        out.flush()?;
+        self.previous = Some(frame);
+        Ok(())
+    }
+}
+
+/// A stand-in for a terminal that understands the escape sequences `Screen`
+/// writes, so tests can check what would end up on screen.
+#[cfg(test)]
+pub(crate) struct TestBackend {
+    pub(crate) grid: Grid,
+    pub(crate) cursor_visible: bool,
+    position: (u16, u16),
+    style: Style,
+    /// Every byte written so far.
+    pub(crate) written: Vec<u8>,
+}
+
+#[cfg(test)]
+impl TestBackend {
+    pub(crate) fn new(width: u16, height: u16) -> Self {
+        Self {
+            grid: Grid::new(width, height),
+            cursor_visible: true,
+            position: (0, 0),
+            style: Style::default(),
+            written: Vec::new(),
+        }
+    }
+
+    /// The cursor, if it is showing.
+    pub(crate) fn cursor(&self) -> Option<(u16, u16)> {
+        Some(self.position).filter(|_| self.cursor_visible)
+    }
+
+    fn apply(&mut self, text: &str) {
+        let mut rest = text;
+        while !rest.is_empty() {
+            if let Some(sequence) = rest.strip_prefix("\x1b[") {
+                let end = sequence
+                    .find(|char: char| char.is_ascii_alphabetic())
+                    .expect("unfinished escape sequence");
+                self.apply_sequence(&sequence[..end], sequence.as_bytes()[end] as char);
+                rest = &sequence[end + 1..];
+                continue;
+            }
+
+            let end = rest.find('\x1b').unwrap_or(rest.len());
+            for grapheme in rest[..end].graphemes(true) {
+                let width = grapheme.width().max(1);
+                let (x, y) = self.position;
+                self.grid.put(x, y, grapheme, width, self.style);
+                self.position = ((x + width as u16).min(self.grid.width), y);
+            }
+            rest = &rest[end..];
+        }
+    }
+
+    fn apply_sequence(&mut self, parameters: &str, command: char) {
+        match (parameters, command) {
+            ("?25", 'l') => self.cursor_visible = false,
+            ("?25", 'h') => self.cursor_visible = true,
+            ("2", 'J') => {
+                self.grid = Grid::new(self.grid.width, self.grid.height);
+            }
+            (parameters, 'H') => {
+                let (row, col) = parameters.split_once(';').unwrap();
+                self.position = (
+                    col.parse::<u16>().unwrap() - 1,
+                    row.parse::<u16>().unwrap() - 1,
+                );
+            }
+            (parameters, 'm') => self.apply_sgr(parameters),
+            _ => panic!("unexpected escape sequence {:?}{}", parameters, command),
+        }
+    }
+
+    fn apply_sgr(&mut self, parameters: &str) {
+        match parameters {
+            "0" => self.style = Style::default(),
+            "1" => self.style.bold = true,
+            "7" => self.style.reverse = true,
+            "27" => self.style.reverse = false,
+            parameters => {
+                // Colors are compared by the sequences crossterm writes for
+                // them
+                let colors = [
+                    Color::Reset,
+                    Color::Red,
+                    Color::Green,
+                    Color::Yellow,
+                    Color::Blue,
+                    Color::Magenta,
+                    Color::Cyan,
+                ];
+                self.style.foreground = colors
+                    .iter()
+                    .copied()
+                    .find(|&color| {
+                        let mut sequence = Vec::new();
+                        queue!(sequence, SetForegroundColor(color)).unwrap();
+                        sequence == format!("\x1b[{}m", parameters).as_bytes()
+                    })
+                    .unwrap_or_else(|| panic!("unexpected SGR {:?}", parameters));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl Write for TestBackend {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.written.extend_from_slice(bytes);
+        self.apply(std::str::from_utf8(bytes).expect("invalid UTF-8 written"));
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: u16, height: u16, rows: &[&str]) -> Grid {
+        let mut frame = Grid::new(width, height);
+        for (y, text) in rows.iter().enumerate() {
+            frame.print(0, y as u16, width, text, Style::default());
+        }
+        frame
+    }
+
+    #[test]
+    fn first_frame_draws_everything() {
+        let mut screen = Screen::default();
+        let mut backend = TestBackend::new(10, 2);
+        let mut grid = frame(10, 2, &["hello"]);
+        let bold = Style {
+            bold: true,
+            ..Style::default()
+        };
+        grid.print(2, 1, 10, "world", bold);
+        grid.cursor = Some((5, 0));
+        screen.draw(grid.clone(), &mut backend).unwrap();
+
+        assert_eq!(backend.grid.row_text(0), "hello     ");
+        assert_eq!(backend.grid.row_text(1), "  world   ");
+        assert_eq!(backend.grid.cell(3, 1).style, bold);
+        assert_eq!(backend.cursor(), Some((5, 0)));
+        grid.cursor = None;
+        assert_eq!(backend.grid, grid);
+    }
+
+    #[test]
+    fn only_changed_cells_are_written() {
+        let mut screen = Screen::default();
+        let mut backend = TestBackend::new(20, 3);
+        screen
+            .draw(frame(20, 3, &["", "some text"]), &mut backend)
+            .unwrap();
+        backend.written.clear();
+        screen
+            .draw(frame(20, 3, &["", "some test"]), &mut backend)
+            .unwrap();
+
+        assert_eq!(backend.grid.row_text(1), "some test           ");
+        let written = String::from_utf8(backend.written.clone()).unwrap();
+        assert_eq!(written, "\x1b[?25l\x1b[2;8H\x1b[0m\x1b[39ms\x1b[0m");
+    }
+
+    #[test]
+    fn unchanged_frame_writes_no_cells() {
+        let mut screen = Screen::default();
+        let mut backend = TestBackend::new(5, 1);
+        screen.draw(frame(5, 1, &["abc"]), &mut backend).unwrap();
+        backend.written.clear();
+        screen.draw(frame(5, 1, &["abc"]), &mut backend).unwrap();
+
+        assert_eq!(backend.written, b"\x1b[?25l");
+    }
+
+    #[test]
+    fn wide_graphemes_take_two_cells() {
+        let mut screen = Screen::default();
+        let mut backend = TestBackend::new(5, 1);
+        let mut grid = Grid::new(5, 1);
+        assert_eq!(grid.print(0, 0, 5, "a漢字", Style::default()), 5);
+        assert_eq!(grid.cell(2, 0).text, "");
+        screen.draw(grid, &mut backend).unwrap();
+        assert_eq!(backend.grid.row_text(0), "a漢字");
+
+        // Narrow graphemes over a wide one redraw both of its halves
+        screen.draw(frame(5, 1, &["abcde"]), &mut backend).unwrap();
+        assert_eq!(backend.grid.row_text(0), "abcde");
+    }
+
+    #[test]
+    fn wide_grapheme_past_the_edge_becomes_spaces() {
+        let mut grid = Grid::new(3, 1);
+        grid.put(0, 0, "a", 1, Style::default());
+        grid.put(2, 0, "漢", 2, Style::default());
+        assert_eq!(grid.row_text(0), "a  ");
+        assert_eq!(grid.print(0, 0, 3, "ab漢", Style::default()), 2);
+    }
+
+    #[test]
+    fn new_size_redraws_everything() {
+        let mut screen = Screen::default();
+        screen
+            .draw(frame(4, 1, &["abcd"]), &mut TestBackend::new(4, 1))
+            .unwrap();
+
+        let mut backend = TestBackend::new(6, 2);
+        screen.draw(frame(6, 2, &["abcd"]), &mut backend).unwrap();
+        assert_eq!(backend.grid, frame(6, 2, &["abcd"]));
+    }
+
+    #[test]
+    fn invalidate_redraws_everything() {
+        let mut screen = Screen::default();
+        let mut backend = TestBackend::new(4, 1);
+        screen.draw(frame(4, 1, &["abcd"]), &mut backend).unwrap();
+
+        // Something else drew over the terminal
+        backend.grid = Grid::new(4, 1);
+        screen.invalidate();
+        screen.draw(frame(4, 1, &["abcd"]), &mut backend).unwrap();
+        assert_eq!(backend.grid.row_text(0), "abcd");
+    }
+}
@@ -0,0 +1,147 @@
+use crossterm::event::KeyCode;
+
+use crate::{buffer::Buffer, prompt::PromptEvent, EditorState};
+
+impl EditorState {
+    pub(crate) fn find(&mut self) -> crossterm::Result<()> {
+        let saved_view = self.window().view;
+
+        self.search_last_match = None;
+        let query = self.prompt(
+            "Search: {} (Use ESC/Arrows/Enter)",
+            Some(EditorState::find_callback),
+        )?;
+        self.search_query = None;
+
+        if query.is_none() {
+            self.windows[self.focused_window].view = saved_view;
+        }
+
+        Ok(())
+    }
+
+    fn find_callback(&mut self, query: &str, event: PromptEvent) -> bool {
+        // Arrows step through the matches, but with no query yet they are
+        // left to the prompt so Up and Down can recall earlier searches
+        let step = match event {
+            PromptEvent::Key(key) if !query.is_empty() => match key.code {
+                KeyCode::Right | KeyCode::Down => Some(true),
+                KeyCode::Left | KeyCode::Up => Some(false),
+                _ => return false,
+            },
+            PromptEvent::Edited => None,
+            _ => return false,
+        };
+
+        self.search_query = if query.is_empty() {
+            None
+        } else {
+            Some(query.to_string())
+        };
+        if query.is_empty() {
+            self.search_last_match = None;
+            return false;
+        }
+
+        let last_match = self.search_last_match;
+        let (buffer, view) = self.buffer_and_view();
+        if buffer.row_count() == 0 {
+            return step.is_some();
+        }
+        let (row, col) = last_match.unwrap_or((view.cursor_row, view.cursor_col));
+        // Past the end of the file there is nothing to search, start over
+        let (row, col) = if row < buffer.row_count() {
+            (row, col)
+        } else {
+            (0, 0)
+        };
+        let found = match step {
+            Some(true) => buffer.find_forward(query, row, col + 1),
+            Some(false) => buffer.find_backward(query, row, col),
+            // The query changed, so the current match may still match it
+            None => buffer.find_forward(query, row, col),
+        };
+
+        if let Some(position) = found {
+            view.set_cursor(position);
+            self.search_last_match = found;
+        }
+
+        step.is_some()
+    }
+
+    /// Render column ranges of every match of the current search in a row,
+    /// used to highlight them while searching.
+    pub(crate) fn search_highlights(&self, row: usize) -> Vec<(usize, usize)> {
+        match &self.search_query {
+            Some(query) => self.buffer().match_ranges(row, query),
+            None => Vec::new(),
+        }
+    }
+}
+
+impl Buffer {
+    /// Char indices of every match of `query` in a row.
+    fn row_matches(&self, row: usize, query: &str) -> Vec<usize> {
+        let text = self.text.row(row);
+        text.match_indices(query)
+            .map(|(index, _)| text[..index].chars().count())
+            .collect()
+    }
+
+    /// Finds the first match at or after `col` in `row`, wrapping around the
+    /// end of the file.
+    fn find_forward(&self, query: &str, row: usize, col: usize) -> Option<(usize, usize)> {
+        let row_count = self.row_count();
+        for i in 0..=row_count {
+            let current = (row + i) % row_count;
+            let matches = self.row_matches(current, query);
+            let found = if i == 0 {
+                matches.into_iter().find(|&at| at >= col)
+            } else {
+                matches.into_iter().next()
+            };
+            if let Some(at) = found {
+                return Some((current, at));
+            }
+        }
+        None
+    }
+
+    /// Finds the last match before `col` in `row`, wrapping around the start
+    /// of the file.
+    fn find_backward(&self, query: &str, row: usize, col: usize) -> Option<(usize, usize)> {
+        let row_count = self.row_count();
+        for i in 0..=row_count {
+            let current = (row + row_count - i) % row_count;
+            let matches = self.row_matches(current, query);
+            let found = if i == 0 {
+                matches.into_iter().rev().find(|&at| at < col)
+            } else {
+                matches.into_iter().next_back()
+            };
+            if let Some(at) = found {
+                return Some((current, at));
+            }
+        }
+        None
+    }
+
+    /// Render column ranges of every match of `query` in a row.
+    fn match_ranges(&self, row: usize, query: &str) -> Vec<(usize, usize)> {
+        let editor_row = match self.row(row) {
+            Some(editor_row) => editor_row,
+            None => return Vec::new(),
+        };
+        let query_len = query.chars().count();
+        self.row_matches(row, query)
+            .into_iter()
+            .map(|at| {
+                (
+                    editor_row.render_col(at),
+                    editor_row.render_col(at + query_len),
+                )
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,792 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crossterm::style::Color;
+use serde::Deserialize;
+
+use crate::{
+    buffer::Buffer,
+    row::{is_escaped, EditorRow},
+    screen::Style,
+};
+
+/// The token class of a single rendered char.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum Highlight {
+    Normal,
+    Keyword,
+    Type,
+    String,
+    Number,
+    Comment,
+    MultilineComment,
+    Match,
+    Selection,
+    /// A char drawn as an escape, such as a control char or an invalid byte.
+    Escape,
+}
+
+impl Highlight {
+    pub(crate) fn color(self) -> Color {
+        match self {
+            Highlight::Normal => Color::Reset,
+            Highlight::Keyword => Color::Yellow,
+            Highlight::Type => Color::Green,
+            Highlight::String => Color::Magenta,
+            Highlight::Number => Color::Red,
+            Highlight::Comment | Highlight::MultilineComment => Color::Cyan,
+            Highlight::Match => Color::Blue,
+            Highlight::Selection => Color::Reset,
+            Highlight::Escape => Color::Reset,
+        }
+    }
+
+    /// Whether the class is drawn in reverse video.
+    pub(crate) fn is_reversed(self) -> bool {
+        matches!(
+            self,
+            Highlight::Match | Highlight::Selection | Highlight::Escape
+        )
+    }
+
+    pub(crate) fn style(self) -> Style {
+        Style {
+            foreground: self.color(),
+            reverse: self.is_reversed(),
+            bold: false,
+        }
+    }
+}
+
+/// What a row ends inside of, which the next row starts inside of.
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub(crate) struct HighlightState {
+    in_comment: bool,
+    in_string: Option<char>,
+}
+
+/// How to highlight one kind of file. Built in definitions live in
+/// `src/syntax/`, and more can be added as TOML files in the user's
+/// `~/.config/kilors/syntax/` directory.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Syntax {
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) extensions: Vec<String>,
+    /// Whole file names such as `Makefile`, for files without an extension.
+    #[serde(default)]
+    pub(crate) file_names: Vec<String>,
+    #[serde(default)]
+    pub(crate) keywords: Vec<String>,
+    #[serde(default)]
+    pub(crate) types: Vec<String>,
+    pub(crate) single_line_comment: Option<String>,
+    pub(crate) multiline_comment: Option<(String, String)>,
+    #[serde(default)]
+    pub(crate) string_quotes: Vec<char>,
+    /// Whether strings can continue onto the next line.
+    #[serde(default)]
+    pub(crate) multiline_strings: bool,
+    #[serde(default)]
+    pub(crate) highlight_numbers: bool,
+    /// Whether `0x` starts a hexadecimal number.
+    #[serde(default)]
+    pub(crate) hex_numbers: bool,
+    /// A char allowed between digits, like the `_` in `1_000`.
+    pub(crate) digit_separator: Option<char>,
+    /// The chars besides whitespace that words end at when moving by words,
+    /// instead of the default punctuation. This is the only place they can
+    /// be configured.
+    pub(crate) word_separators: Option<String>,
+}
+
+const BUILTIN_SYNTAXES: &[(&str, &str)] = &[
+    ("rust.toml", include_str!("syntax/rust.toml")),
+    ("c.toml", include_str!("syntax/c.toml")),
+    ("python.toml", include_str!("syntax/python.toml")),
+    ("toml.toml", include_str!("syntax/toml.toml")),
+    ("markdown.toml", include_str!("syntax/markdown.toml")),
+    ("makefile.toml", include_str!("syntax/makefile.toml")),
+];
+
+pub(crate) struct SyntaxDatabase {
+    syntaxes: Vec<Rc<Syntax>>,
+    /// Problems with the user's syntax files, to be shown once at startup.
+    pub(crate) errors: Vec<String>,
+}
+
+impl SyntaxDatabase {
+    /// Loads the built in syntaxes and the ones in the user's config
+    /// directory.
+    pub(crate) fn load() -> Self {
+        Self::load_from(user_syntax_dir().as_deref())
+    }
+
+    /// Loads the built in syntaxes and, if given, the ones in `user_dir`.
+    pub(crate) fn load_from(user_dir: Option<&Path>) -> Self {
+        let mut database = Self {
+            syntaxes: Vec::new(),
+            errors: Vec::new(),
+        };
+
+        // User definitions come first so they override the built in ones
+        if let Some(dir) = user_dir {
+            database.load_dir(dir);
+        }
+        for (file_name, contents) in BUILTIN_SYNTAXES {
+            match toml::from_str(contents) {
+                Ok(syntax) => database.syntaxes.push(Rc::new(syntax)),
+                Err(e) => panic!("invalid built in syntax {}: {}", file_name, e),
+            }
+        }
+
+        database
+    }
+
+    fn load_dir(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                self.errors
+                    .push(format!("Can't read {}: {}", dir.display(), e));
+                return;
+            }
+        };
+
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            let syntax = fs::read_to_string(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|contents| toml::from_str(&contents).map_err(|e| e.to_string()));
+            match syntax {
+                Ok(syntax) => self.syntaxes.push(Rc::new(syntax)),
+                Err(e) => {
+                    self.errors
+                        .push(format!("Invalid syntax file {}: {}", path.display(), e))
+                }
+            }
+        }
+    }
+
+    pub(crate) fn select(&self, file_name: &str) -> Option<Rc<Syntax>> {
+        let path = Path::new(file_name);
+        let name = path.file_name().and_then(|name| name.to_str());
+        let extension = path.extension().and_then(|ext| ext.to_str());
+
+        self.syntaxes
+            .iter()
+            .find(|syntax| {
+                name.is_some_and(|name| syntax.file_names.iter().any(|n| n == name))
+                    || extension.is_some_and(|ext| syntax.extensions.iter().any(|e| e == ext))
+            })
+            .cloned()
+    }
+}
+
+fn user_syntax_dir() -> Option<PathBuf> {
+    let config_dir = match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_dir.join("kilors").join("syntax"))
+}
+
+fn is_separator(char: char) -> bool {
+    char.is_whitespace() || char == '\0' || ",.()+-/*=~%<>[];{}&|!:?^".contains(char)
+}
+
+fn starts_with(text: &[char], pattern: &str) -> bool {
+    let mut text = text.iter();
+    pattern.chars().all(|char| text.next() == Some(&char))
+}
+
+impl Syntax {
+    /// The state a row of raw text ends inside of when it starts inside
+    /// `state`. Only comments and strings matter for that, so this skips
+    /// rendering the row and looking for keywords and numbers, which makes
+    /// it cheap enough to run over every row above the screen.
+    fn end_state(&self, text: &str, state: HighlightState) -> HighlightState {
+        let mut in_comment = state.in_comment && self.multiline_comment.is_some();
+        let mut in_string = state.in_string.filter(|_| self.multiline_strings);
+        let single_line = self.single_line_comment.as_deref();
+        let multiline = self
+            .multiline_comment
+            .as_ref()
+            .map(|(start, end)| (start.as_str(), end.as_str()));
+        // The chars a comment or string can start at, or any char at all if
+        // a delimiter is empty
+        let starts = [single_line, multiline.map(|(start, _)| start)];
+        let any_char = starts.iter().flatten().any(|start| start.is_empty());
+        let [single_line_first, multiline_first] =
+            starts.map(|start| start.and_then(|start| start.chars().next()));
+        let could_start = |char: char| {
+            any_char
+                || Some(char) == single_line_first
+                || Some(char) == multiline_first
+                || self.string_quotes.contains(&char)
+        };
+
+        let mut rest = text;
+        while !rest.is_empty() {
+            if let (true, Some((_, end))) = (in_comment, multiline) {
+                match rest.find(end) {
+                    Some(index) => {
+                        rest = &rest[index + end.len()..];
+                        in_comment = false;
+                    }
+                    None => break,
+                }
+            } else if let Some(quote) = in_string {
+                match rest.find(['\\', quote]) {
+                    // An escaped char never ends the string
+                    Some(index) if rest[index..].starts_with('\\') => {
+                        let escaped = &rest[index + 1..];
+                        rest = &escaped[escaped.chars().next().map_or(0, char::len_utf8)..];
+                    }
+                    Some(index) => {
+                        rest = &rest[index + quote.len_utf8()..];
+                        in_string = None;
+                    }
+                    None => break,
+                }
+            } else {
+                match rest.find(could_start) {
+                    Some(index) => rest = &rest[index..],
+                    None => break,
+                }
+                if single_line.is_some_and(|comment| rest.starts_with(comment)) {
+                    break;
+                }
+                if let Some((start, _)) = multiline.filter(|(start, _)| rest.starts_with(start)) {
+                    rest = &rest[start.len()..];
+                    in_comment = true;
+                    continue;
+                }
+                let char = rest.chars().next().unwrap();
+                if self.string_quotes.contains(&char) {
+                    in_string = Some(char);
+                }
+                rest = &rest[char.len_utf8()..];
+            }
+        }
+
+        HighlightState {
+            in_comment,
+            in_string: in_string.filter(|_| self.multiline_strings),
+        }
+    }
+}
+
+impl EditorRow {
+    /// Highlights the row starting inside `state`, and returns the state it
+    /// ends inside of.
+    fn highlight(&mut self, syntax: Option<&Syntax>, state: HighlightState) -> HighlightState {
+        let state = self.highlight_syntax(syntax, state);
+        for (index, char) in self.text_raw.chars().enumerate() {
+            if is_escaped(char) {
+                let (start, end) = (self.render_cols[index], self.render_cols[index + 1]);
+                for highlight in &mut self.highlight[start..end] {
+                    *highlight = Highlight::Escape;
+                }
+            }
+        }
+        state
+    }
+
+    fn highlight_syntax(
+        &mut self,
+        syntax: Option<&Syntax>,
+        state: HighlightState,
+    ) -> HighlightState {
+        // Highlighting only looks at the first char of every column, which
+        // is enough to find the ASCII that tokens are made of
+        let render: Vec<char> = (0..self.render_len())
+            .map(|col| self.cell(col).chars().next().unwrap_or('\0'))
+            .collect();
+        self.highlight = vec![Highlight::Normal; render.len()];
+        let syntax = match syntax {
+            Some(syntax) => syntax,
+            None => return HighlightState::default(),
+        };
+
+        let mut in_comment = state.in_comment && syntax.multiline_comment.is_some();
+        let mut in_string = state.in_string.filter(|_| syntax.multiline_strings);
+        let mut prev_sep = true;
+        let mut i = 0;
+
+        'chars: while i < render.len() {
+            let char = render[i];
+            let prev_highlight = if i > 0 {
+                self.highlight[i - 1]
+            } else {
+                Highlight::Normal
+            };
+
+            if let Some(comment) = &syntax.single_line_comment {
+                if in_string.is_none() && !in_comment && starts_with(&render[i..], comment) {
+                    for highlight in &mut self.highlight[i..] {
+                        *highlight = Highlight::Comment;
+                    }
+                    break;
+                }
+            }
+
+            if let Some((start, end)) = &syntax.multiline_comment {
+                if in_comment {
+                    if starts_with(&render[i..], end) {
+                        let len = end.chars().count();
+                        for highlight in &mut self.highlight[i..i + len] {
+                            *highlight = Highlight::MultilineComment;
+                        }
+                        i += len;
+                        in_comment = false;
+                        prev_sep = true;
+                    } else {
+                        self.highlight[i] = Highlight::MultilineComment;
+                        i += 1;
+                    }
+                    continue;
+                } else if in_string.is_none() && starts_with(&render[i..], start) {
+                    let len = start.chars().count();
+                    for highlight in &mut self.highlight[i..i + len] {
+                        *highlight = Highlight::MultilineComment;
+                    }
+                    i += len;
+                    in_comment = true;
+                    continue;
+                }
+            }
+
+            if let Some(quote) = in_string {
+                self.highlight[i] = Highlight::String;
+                if char == '\\' && i + 1 < render.len() {
+                    self.highlight[i + 1] = Highlight::String;
+                    i += 2;
+                    continue;
+                }
+                if char == quote {
+                    in_string = None;
+                }
+                i += 1;
+                prev_sep = true;
+                continue;
+            } else if syntax.string_quotes.contains(&char) {
+                in_string = Some(char);
+                self.highlight[i] = Highlight::String;
+                i += 1;
+                continue;
+            }
+
+            if syntax.highlight_numbers {
+                if syntax.hex_numbers
+                    && prev_sep
+                    && (starts_with(&render[i..], "0x") || starts_with(&render[i..], "0X"))
+                {
+                    let mut end = i + 2;
+                    while end < render.len()
+                        && (render[end].is_ascii_hexdigit()
+                            || Some(render[end]) == syntax.digit_separator)
+                    {
+                        end += 1;
+                    }
+                    for highlight in &mut self.highlight[i..end] {
+                        *highlight = Highlight::Number;
+                    }
+                    i = end;
+                    prev_sep = false;
+                    continue;
+                }
+
+                let continues_number = prev_highlight == Highlight::Number
+                    && (char == '.' || Some(char) == syntax.digit_separator);
+                if (char.is_ascii_digit() && (prev_sep || prev_highlight == Highlight::Number))
+                    || continues_number
+                {
+                    self.highlight[i] = Highlight::Number;
+                    i += 1;
+                    prev_sep = false;
+                    continue;
+                }
+            }
+
+            if prev_sep {
+                let words = syntax
+                    .keywords
+                    .iter()
+                    .map(|word| (word, Highlight::Keyword))
+                    .chain(syntax.types.iter().map(|word| (word, Highlight::Type)));
+                for (word, class) in words {
+                    let len = word.chars().count();
+                    if starts_with(&render[i..], word)
+                        && render.get(i + len).is_none_or(|&next| is_separator(next))
+                    {
+                        for highlight in &mut self.highlight[i..i + len] {
+                            *highlight = class;
+                        }
+                        i += len;
+                        prev_sep = false;
+                        continue 'chars;
+                    }
+                }
+            }
+
+            prev_sep = is_separator(char);
+            i += 1;
+        }
+
+        HighlightState {
+            in_comment,
+            in_string: in_string.filter(|_| syntax.multiline_strings),
+        }
+    }
+}
+
+impl Buffer {
+    /// Forgets the highlight state of `from` and every row after it.
+    pub(crate) fn invalidate_highlight(&mut self, from: usize) {
+        self.highlight_states.truncate(from);
+        self.highlight_dirty = self.highlight_dirty.filter(|&(first, _)| first < from);
+    }
+
+    /// Keeps the stored highlight states lined up with the rows after an
+    /// edit at `row` that inserted (or with a negative count, removed)
+    /// rows after it, and marks the edited rows for highlighting again.
+    pub(crate) fn highlight_edited(&mut self, row: usize, inserted: isize) {
+        let len = self.highlight_states.len();
+        if row >= len {
+            return;
+        }
+        let after = row + 1;
+        if inserted > 0 {
+            let new_states = std::iter::repeat_n(HighlightState::default(), inserted as usize);
+            self.highlight_states.splice(after..after, new_states);
+        } else if inserted < 0 {
+            let end = (after + inserted.unsigned_abs()).min(len);
+            self.highlight_states.drain(after.min(len)..end);
+        }
+
+        let last_edited = row + inserted.max(0) as usize;
+        self.highlight_dirty = Some(match self.highlight_dirty {
+            Some((first, last)) => {
+                // Rows the edit added or removed move the earlier edits
+                // after it along
+                let last = if last > row {
+                    last.saturating_add_signed(inserted).max(row)
+                } else {
+                    last
+                };
+                (first.min(row), last.max(last_edited))
+            }
+            None => (row, last_edited),
+        });
+    }
+
+    /// Works out the state every row before `end` starts inside of, without
+    /// highlighting them in full. After an edit, the rows from it onwards are highlighted
+    /// again until one ends in the same state as before, since the rows
+    /// after that can't have changed.
+    pub(crate) fn update_highlight(&mut self, end: usize) {
+        let syntax = match self.syntax.as_deref() {
+            Some(syntax) => syntax,
+            // Without a syntax every row starts in the default state
+            None => return,
+        };
+        let row_count = self.text.row_count();
+        let end = end.min(row_count);
+        self.highlight_states.truncate(row_count);
+
+        if let Some((first, last)) = self.highlight_dirty.take() {
+            let mut state = self.highlight_state_before(first);
+            let stored = self.highlight_states.len();
+            for (row, text) in (first..stored).zip(self.text.rows_from(first)) {
+                let new_state = syntax.end_state(&text, state);
+                let unchanged = self.highlight_states[row] == new_state;
+                self.highlight_states[row] = new_state;
+                if unchanged && row >= last {
+                    break;
+                }
+                // Past the screen the rest can wait until it is needed
+                if row + 1 >= end && row + 1 > last {
+                    self.highlight_states.truncate(row + 1);
+                    break;
+                }
+                state = new_state;
+            }
+        }
+
+        let start = self.highlight_states.len();
+        if start < end {
+            let mut state = self.highlight_state_before(start);
+            for text in self.text.rows_from(start).take(end - start) {
+                state = syntax.end_state(&text, state);
+                self.highlight_states.push(state);
+            }
+        }
+    }
+
+    fn highlight_state_before(&self, row: usize) -> HighlightState {
+        match row.checked_sub(1) {
+            Some(previous) => self
+                .highlight_states
+                .get(previous)
+                .copied()
+                .unwrap_or_default(),
+            None => HighlightState::default(),
+        }
+    }
+
+    /// Renders and highlights a row, which needs `update_highlight` to have
+    /// reached it first.
+    pub(crate) fn highlighted_row(&self, row: usize) -> Option<EditorRow> {
+        let mut editor_row = self.row(row)?;
+        editor_row.highlight(self.syntax.as_deref(), self.highlight_state_before(row));
+        Some(editor_row)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{format::FileFormat, text::Text, window::View};
+
+    fn rust_buffer(text: &str) -> Buffer {
+        let mut buffer = Buffer::new();
+        buffer.text = Text::load(text.as_bytes(), &FileFormat::default()).unwrap();
+        buffer.syntax = SyntaxDatabase::load_from(None).select("main.rs");
+        buffer
+    }
+
+    fn highlight(buffer: &mut Buffer, row: usize) -> Vec<Highlight> {
+        buffer.update_highlight(row + 1);
+        buffer.highlighted_row(row).unwrap().highlight
+    }
+
+    fn name(database: &SyntaxDatabase, file_name: &str) -> Option<String> {
+        database.select(file_name).map(|syntax| syntax.name.clone())
+    }
+
+    #[test]
+    fn selects_by_extension_or_file_name() {
+        let database = SyntaxDatabase::load_from(None);
+        assert!(database.errors.is_empty());
+        assert_eq!(name(&database, "src/main.rs").as_deref(), Some("rust"));
+        assert_eq!(name(&database, "a.h").as_deref(), Some("c"));
+        assert_eq!(name(&database, "setup.py").as_deref(), Some("python"));
+        assert_eq!(name(&database, "README.md").as_deref(), Some("markdown"));
+        assert_eq!(name(&database, "Makefile").as_deref(), Some("makefile"));
+        assert_eq!(
+            name(&database, "dir/GNUmakefile").as_deref(),
+            Some("makefile")
+        );
+        assert_eq!(name(&database, "Cargo.lock").as_deref(), Some("toml"));
+        assert_eq!(name(&database, "notes.txt"), None);
+        assert_eq!(name(&database, "LICENSE"), None);
+        // Names are matched whole, and extensions don't match names
+        assert_eq!(name(&database, "Makefile.bak"), None);
+        assert_eq!(name(&database, "rs"), None);
+    }
+
+    #[test]
+    fn user_syntaxes_override_built_in_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("rust.toml"),
+            "name = \"mine\"\nextensions = [\"rs\"]\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("log.toml"),
+            "name = \"log\"\nfile_names = [\"Makefile\"]\nextensions = [\"log\"]\n",
+        )
+        .unwrap();
+        // Only TOML files are syntaxes
+        fs::write(dir.path().join("c.txt"), "not a syntax").unwrap();
+
+        let database = SyntaxDatabase::load_from(Some(dir.path()));
+        assert!(database.errors.is_empty());
+        assert_eq!(name(&database, "main.rs").as_deref(), Some("mine"));
+        assert_eq!(name(&database, "Makefile").as_deref(), Some("log"));
+        assert_eq!(name(&database, "server.log").as_deref(), Some("log"));
+        assert_eq!(name(&database, "main.c").as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn reports_invalid_user_syntaxes() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.toml"), "name = 1\n").unwrap();
+        fs::write(
+            dir.path().join("b.toml"),
+            "name = \"b\"\nextensions = [\"b\"]\ncolour = \"red\"\n",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("c.toml"),
+            "name = \"c2\"\nextensions = [\"c\"]\n",
+        )
+        .unwrap();
+
+        let database = SyntaxDatabase::load_from(Some(dir.path()));
+        assert_eq!(database.errors.len(), 2);
+        assert!(database.errors[0].starts_with("Invalid syntax file"));
+        assert!(database.errors[0].contains("a.toml"));
+        assert!(database.errors[1].contains("b.toml"));
+        assert!(database.errors[1].contains("colour"));
+        // The valid files and the built in syntaxes still load
+        assert_eq!(name(&database, "main.c").as_deref(), Some("c2"));
+        assert_eq!(name(&database, "x.b"), None);
+        assert_eq!(name(&database, "main.rs").as_deref(), Some("rust"));
+
+        // A missing directory is just no user syntaxes
+        let database = SyntaxDatabase::load_from(Some(&dir.path().join("missing")));
+        assert!(database.errors.is_empty());
+    }
+
+    #[test]
+    fn multiline_comments_cross_rows() {
+        let mut buffer = rust_buffer("a /* b\nc\nd */ e\nf\n");
+        use Highlight::{MultilineComment as C, Normal as N};
+        assert_eq!(highlight(&mut buffer, 0), [N, N, C, C, C, C]);
+        assert_eq!(highlight(&mut buffer, 1), [C]);
+        assert_eq!(highlight(&mut buffer, 2), [C, C, C, C, N, N]);
+        assert_eq!(highlight(&mut buffer, 3), [N]);
+    }
+
+    #[test]
+    fn multiline_strings_cross_rows() {
+        let mut buffer = rust_buffer("\"a\nb\nc\" d\n");
+        use Highlight::{Normal as N, String as S};
+        assert_eq!(highlight(&mut buffer, 0), [S, S]);
+        assert_eq!(highlight(&mut buffer, 1), [S]);
+        assert_eq!(highlight(&mut buffer, 2), [S, S, N, N]);
+    }
+
+    #[test]
+    fn escapes_are_highlighted() {
+        let text = format!("\"\x01\" {}\n", char::from_u32(0x10ff80).unwrap());
+        let mut buffer = rust_buffer(&text);
+        use Highlight::{Escape as E, Normal as N, String as S};
+        assert_eq!(highlight(&mut buffer, 0), [S, E, E, S, N, E, E, E, E]);
+
+        // Even without a syntax
+        buffer.syntax = None;
+        assert_eq!(highlight(&mut buffer, 0), [N, E, E, N, N, E, E, E, E]);
+    }
+
+    #[test]
+    fn edits_reach_the_rows_after_them() {
+        let text: String = (0..10_000).map(|row| format!("x{}\n", row)).collect();
+        let mut buffer = rust_buffer(&text);
+        let mut view = View::default();
+        assert_eq!(highlight(&mut buffer, 9_999)[0], Highlight::Normal);
+
+        // Opening a comment on the first row comments out every row
+        buffer.insert_char(&mut view, '/');
+        buffer.insert_char(&mut view, '*');
+        assert_eq!(
+            highlight(&mut buffer, 5_000)[0],
+            Highlight::MultilineComment
+        );
+        assert_eq!(
+            highlight(&mut buffer, 9_999)[0],
+            Highlight::MultilineComment
+        );
+
+        // And closing it again on the row after brings them back
+        view.set_cursor((1, 0));
+        buffer.insert_char(&mut view, '*');
+        buffer.insert_char(&mut view, '/');
+        assert_eq!(highlight(&mut buffer, 1)[0], Highlight::MultilineComment);
+        assert_eq!(highlight(&mut buffer, 2)[0], Highlight::Normal);
+        assert_eq!(highlight(&mut buffer, 9_999)[0], Highlight::Normal);
+
+        // Splitting and joining rows keeps the states lined up
+        view.set_cursor((0, 2));
+        buffer.insert_newline(&mut view);
+        assert_eq!(highlight(&mut buffer, 1)[0], Highlight::MultilineComment);
+        assert_eq!(highlight(&mut buffer, 3)[0], Highlight::Normal);
+        buffer.delete_char(&mut view);
+        assert_eq!(highlight(&mut buffer, 1)[0], Highlight::MultilineComment);
+        assert_eq!(highlight(&mut buffer, 2)[0], Highlight::Normal);
+    }
+
+    #[test]
+    fn scanning_ends_rows_like_highlighting_them() {
+        let database = SyntaxDatabase::load_from(None);
+        let rows = [
+            "",
+            "fn main() { let x = 0x1F; }",
+            "a /* b */ c /* d",
+            "end */ \"open",
+            "\"a\\\"b\" // \"c /*",
+            "'x' \"tail\\",
+            "\t\"tab\t\" # hash",
+            "caf\u{e9} \"\u{6f22}\u{5b57}\" /* \u{1f600}",
+            "ctrl \u{1} \"\u{7f}\" */ x",
+            "*/*/",
+            "# comment \"",
+            "\"\"\" doc",
+        ];
+        let states = [
+            HighlightState::default(),
+            HighlightState {
+                in_comment: true,
+                in_string: None,
+            },
+            HighlightState {
+                in_comment: false,
+                in_string: Some('"'),
+            },
+            HighlightState {
+                in_comment: false,
+                in_string: Some('\''),
+            },
+        ];
+        for file_name in ["a.rs", "a.c", "a.py", "a.toml", "Makefile"] {
+            let syntax = database.select(file_name).unwrap();
+            for row in rows {
+                for state in states {
+                    let mut editor_row = EditorRow::from(row.to_string(), 8);
+                    assert_eq!(
+                        syntax.end_state(row, state),
+                        editor_row.highlight(Some(&syntax), state),
+                        "{} {:?} from {:?}",
+                        file_name,
+                        row,
+                        state
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn stops_once_a_row_ends_like_before() {
+        let text: String = (0..100).map(|row| format!("x{}\n", row)).collect();
+        let mut buffer = rust_buffer(&text);
+        let mut view = View::default();
+        buffer.update_highlight(100);
+
+        // A state that highlighting could never give this row is kept, since
+        // an edit that doesn't change where the first row ends can't reach it
+        let planted = HighlightState {
+            in_comment: true,
+            in_string: None,
+        };
+        buffer.highlight_states[50] = planted;
+        buffer.insert_char(&mut view, 'y');
+        buffer.update_highlight(100);
+        assert_eq!(buffer.highlight_states[50], planted);
+        assert!(buffer.highlight_dirty.is_none());
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    io::{self, stdout, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crossterm::{
+    event::{self, DisableMouseCapture, EnableMouseCapture, Event},
+    execute,
+    terminal::{
+        self, disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+        SetTitle,
+    },
+};
+
+/// Everything the editor needs from the terminal it runs in. Whatever is
+/// written to it is drawn on the screen.
+pub(crate) trait Terminal: Write {
+    /// Switches to raw mode on the alternate screen, with mouse capture.
+    fn setup(&mut self) -> crossterm::Result<()>;
+    /// Undoes `setup`.
+    fn cleanup(&mut self) -> crossterm::Result<()>;
+    /// The number of columns and rows.
+    fn size(&self) -> crossterm::Result<(u16, u16)>;
+    /// Waits for the next key press, mouse event or resize.
+    fn read(&mut self) -> crossterm::Result<Event>;
+    fn set_title(&mut self, title: &str) -> crossterm::Result<()>;
+}
+
+/// Whether the real terminal is set up. Shared with the panic hook, so the
+/// terminal is restored exactly once, and only after it was set up.
+static SET_UP: AtomicBool = AtomicBool::new(false);
+
+/// The real terminal, through crossterm. Once set up, it is restored when
+/// dropped, including on early returns.
+pub(crate) struct CrosstermTerminal;
+
+impl Terminal for CrosstermTerminal {
+    fn setup(&mut self) -> crossterm::Result<()> {
+        // Set first so a half finished setup is undone too
+        SET_UP.store(true, Ordering::SeqCst);
+        execute!(stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+        enable_raw_mode()
+    }
+
+    fn cleanup(&mut self) -> crossterm::Result<()> {
+        cleanup()
+    }
+
+    fn size(&self) -> crossterm::Result<(u16, u16)> {
+        terminal::size()
+    }
+
+    fn read(&mut self) -> crossterm::Result<Event> {
+        event::read()
+    }
+
+    fn set_title(&mut self, title: &str) -> crossterm::Result<()> {
+        execute!(stdout(), SetTitle(title))
+    }
+}
+
+impl Write for CrosstermTerminal {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        stdout().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        stdout().flush()
+    }
+}
+
+impl Drop for CrosstermTerminal {
+    fn drop(&mut self) {
+        let _ = cleanup();
+    }
+}
+
+/// Leaves raw mode and the alternate screen, if the terminal is set up.
+/// Also called by the panic hook, which has no terminal to call it on.
+pub(crate) fn cleanup() -> crossterm::Result<()> {
+    if !SET_UP.swap(false, Ordering::SeqCst) {
+        return Ok(());
+    }
+    disable_raw_mode()?;
+    execute!(stdout(), DisableMouseCapture, LeaveAlternateScreen)?;
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) use fake::{FakeScreen, FakeTerminal};
+
+#[cfg(test)]
+mod fake {
+    use std::{cell::RefCell, collections::VecDeque, io, rc::Rc};
+
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers, MouseEvent, MouseEventKind};
+
+    use super::Terminal;
+    use crate::screen::TestBackend;
+
+    /// What a `FakeTerminal` shows, shared with the test driving it.
+    pub(crate) struct FakeScreen {
+        pub(crate) backend: TestBackend,
+        pub(crate) title: String,
+        pub(crate) size: (u16, u16),
+    }
+
+    /// A terminal in memory that plays back scripted events and keeps what
+    /// is drawn on it. Once the events run out, reading fails, which ends
+    /// the event loop.
+    pub(crate) struct FakeTerminal {
+        events: VecDeque<Event>,
+        screen: Rc<RefCell<FakeScreen>>,
+    }
+
+    impl FakeTerminal {
+        pub(crate) fn new(columns: u16, rows: u16) -> (Self, Rc<RefCell<FakeScreen>>) {
+            let screen = Rc::new(RefCell::new(FakeScreen {
+                backend: TestBackend::new(columns, rows),
+                title: String::new(),
+                size: (columns, rows),
+            }));
+            let terminal = Self {
+                events: VecDeque::new(),
+                screen: Rc::clone(&screen),
+            };
+            (terminal, screen)
+        }
+
+        pub(crate) fn push(&mut self, event: Event) -> &mut Self {
+            self.events.push_back(event);
+            self
+        }
+
+        /// Queues a key press for each char of `text`.
+        pub(crate) fn type_text(&mut self, text: &str) -> &mut Self {
+            for char in text.chars() {
+                self.key(KeyCode::Char(char), KeyModifiers::NONE);
+            }
+            self
+        }
+
+        pub(crate) fn key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> &mut Self {
+            self.push(Event::Key(KeyEvent { code, modifiers }))
+        }
+
+        pub(crate) fn ctrl(&mut self, char: char) -> &mut Self {
+            self.key(KeyCode::Char(char), KeyModifiers::CONTROL)
+        }
+
+        pub(crate) fn mouse(&mut self, kind: MouseEventKind, column: u16, row: u16) -> &mut Self {
+            self.push(Event::Mouse(MouseEvent {
+                kind,
+                column,
+                row,
+                modifiers: KeyModifiers::NONE,
+            }))
+        }
+    }
+
+    impl Terminal for FakeTerminal {
+        fn setup(&mut self) -> crossterm::Result<()> {
+            Ok(())
+        }
+
+        fn cleanup(&mut self) -> crossterm::Result<()> {
+            Ok(())
+        }
+
+        fn size(&self) -> crossterm::Result<(u16, u16)> {
+            Ok(self.screen.borrow().size)
+        }
+
+        fn read(&mut self) -> crossterm::Result<Event> {
+            let event = self.events.pop_front().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::UnexpectedEof, "no more scripted events")
+            })?;
+            // A resize changes what the screen can show from then on
+            if let Event::Resize(columns, rows) = event {
+                let mut screen = self.screen.borrow_mut();
+                screen.size = (columns, rows);
+                screen.backend = TestBackend::new(columns, rows);
+            }
+            Ok(event)
+        }
+
+        fn set_title(&mut self, title: &str) -> crossterm::Result<()> {
+            self.screen.borrow_mut().title = title.to_string();
+            Ok(())
+        }
+    }
+
+    impl io::Write for FakeTerminal {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.screen.borrow_mut().backend.write(bytes)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
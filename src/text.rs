@@ -0,0 +1,201 @@
+use std::{
+    borrow::Cow,
+    io::{self, BufRead, Write},
+};
+
+use ropey::{iter::Chunks, Rope, RopeBuilder};
+
+use crate::format::FileFormat;
+
+/// The text of a buffer, stored as a rope so edits anywhere in a huge file
+/// stay cheap. Every row, including the last, ends with a '\n', so the rope
+/// of an empty buffer is empty and has no rows at all. How the file really
+/// ends its lines is up to its `FileFormat`.
+pub(crate) struct Text {
+    rope: Rope,
+}
+
+impl Text {
+    pub(crate) fn new() -> Self {
+        Self { rope: Rope::new() }
+    }
+
+    /// Reads rows from `reader` a line at a time, without ever holding the
+    /// whole file in memory as a single string.
+    pub(crate) fn load(mut reader: impl BufRead, format: &FileFormat) -> io::Result<Self> {
+        let mut builder = RopeBuilder::new();
+        let mut line = Vec::new();
+        let mut first = true;
+        loop {
+            line.clear();
+            if reader.read_until(b'\n', &mut line)? == 0 {
+                break;
+            }
+            let mut row = format.decode_line(&line, first)?;
+            row.push('\n');
+            builder.append(&row);
+            first = false;
+        }
+        Ok(Self {
+            rope: builder.finish(),
+        })
+    }
+
+    pub(crate) fn row_count(&self) -> usize {
+        self.rope.len_lines() - 1
+    }
+
+    /// The text of a row, without its line ending.
+    pub(crate) fn row(&self, row: usize) -> String {
+        let line = self.rope.line(row);
+        line.slice(..line.len_chars() - 1).to_string()
+    }
+
+    /// The rows from `row` on, without their line endings. Going through
+    /// them in order like this is much quicker than asking for every row.
+    pub(crate) fn rows_from(&self, row: usize) -> Rows<'_> {
+        let start = self.rope.line_to_byte(row);
+        let (mut chunks, chunk_start, _, _) = self.rope.chunks_at_byte(start);
+        let chunk = chunks
+            .next()
+            .map_or("", |chunk| &chunk[start - chunk_start..]);
+        Rows {
+            chunks,
+            chunk,
+            remaining: self.row_count().saturating_sub(row),
+        }
+    }
+
+    /// The number of chars in a row, not counting its line ending.
+    pub(crate) fn row_len(&self, row: usize) -> usize {
+        self.rope.line(row).len_chars() - 1
+    }
+
+    pub(crate) fn char_at(&self, row: usize, col: usize) -> char {
+        self.rope.line(row).char(col)
+    }
+
+    fn char_index(&self, row: usize, col: usize) -> usize {
+        self.rope.line_to_char(row) + col
+    }
+
+    pub(crate) fn insert_char(&mut self, row: usize, col: usize, char: char) {
+        let index = self.char_index(row, col);
+        self.rope.insert_char(index, char);
+    }
+
+    pub(crate) fn delete_char(&mut self, row: usize, col: usize) {
+        if col >= self.row_len(row) {
+            return;
+        }
+        let index = self.char_index(row, col);
+        self.rope.remove(index..index + 1);
+    }
+
+    pub(crate) fn split_row(&mut self, row: usize, col: usize) {
+        self.insert_char(row, col, '\n');
+    }
+
+    /// Joins the row after `row` onto the end of it.
+    pub(crate) fn join_rows(&mut self, row: usize) {
+        let index = self.char_index(row, self.row_len(row));
+        self.rope.remove(index..index + 1);
+    }
+
+    pub(crate) fn insert_row(&mut self, row: usize) {
+        let index = self.rope.line_to_char(row);
+        self.rope.insert_char(index, '\n');
+    }
+
+    pub(crate) fn delete_row(&mut self, row: usize) {
+        let start = self.rope.line_to_char(row);
+        let end = self.rope.line_to_char(row + 1);
+        self.rope.remove(start..end);
+    }
+
+    /// Writes the text out in `format`, returning the number of bytes
+    /// written.
+    pub(crate) fn write_to(
+        &self,
+        mut writer: impl Write,
+        format: &FileFormat,
+    ) -> io::Result<usize> {
+        let row_count = self.row_count();
+        let mut bytes = format.bom().to_vec();
+        let mut written = 0;
+        for (index, line) in self.rope.lines().take(row_count).enumerate() {
+            let row = line.slice(..line.len_chars() - 1);
+            for chunk in row.chunks() {
+                format.encode(chunk, &mut bytes)?;
+            }
+            if index + 1 < row_count || format.final_newline {
+                bytes.extend_from_slice(format.line_ending().as_bytes());
+            }
+            writer.write_all(&bytes)?;
+            written += bytes.len();
+            bytes.clear();
+        }
+        writer.flush()?;
+        Ok(written)
+    }
+}
+
+/// The rows of a `Text` in order, from `Text::rows_from`.
+pub(crate) struct Rows<'a> {
+    chunks: Chunks<'a>,
+    /// What is left of the chunk the next row starts in.
+    chunk: &'a str,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Rows<'a> {
+    type Item = Cow<'a, str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        // Only rows split across chunks need copying
+        let mut split = String::new();
+        loop {
+            if let Some(end) = self.chunk.find('\n') {
+                let row = &self.chunk[..end];
+                self.chunk = &self.chunk[end + 1..];
+                if split.is_empty() {
+                    return Some(Cow::Borrowed(row));
+                }
+                split.push_str(row);
+                return Some(Cow::Owned(split));
+            }
+            split.push_str(self.chunk);
+            self.chunk = self.chunks.next()?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn goes_through_rows_split_across_chunks() {
+        // Rows long enough to span the rope's chunks, some with multi-byte
+        // chars at the edges
+        let rows: Vec<String> = (0..300)
+            .map(|row| format!("é{}ü", "x".repeat(row * 7 % 2500)))
+            .collect();
+        let text = rows
+            .iter()
+            .map(|row| format!("{}\n", row))
+            .collect::<String>();
+        let text = Text::load(text.as_bytes(), &FileFormat::default()).unwrap();
+
+        for start in [0, 1, 150, 299, 300] {
+            let from: Vec<String> = text.rows_from(start).map(Cow::into_owned).collect();
+            assert_eq!(from, rows[start..]);
+        }
+        assert_eq!(Text::new().rows_from(0).count(), 0);
+    }
+}
@@ -0,0 +1,369 @@
+use std::{collections::VecDeque, mem};
+
+use crate::{buffer::Buffer, window::View};
+
+/// Roughly how much memory the undo and redo history may use before the
+/// oldest steps are forgotten.
+const UNDO_MEMORY_LIMIT: usize = 16 * 1024 * 1024;
+
+/// A single change to the rows. Every operation can be reversed by applying
+/// its inverse.
+#[derive(Clone, Copy)]
+pub(crate) enum Operation {
+    InsertChar {
+        row: usize,
+        col: usize,
+        char: char,
+    },
+    DeleteChar {
+        row: usize,
+        col: usize,
+        char: char,
+    },
+    /// Splits `row` at `col`, moving the rest of it to a new row below.
+    SplitLine {
+        row: usize,
+        col: usize,
+    },
+    /// Appends the row below onto `row`, which was `col` chars long.
+    JoinLine {
+        row: usize,
+        col: usize,
+    },
+    InsertRow {
+        row: usize,
+    },
+    DeleteRow {
+        row: usize,
+    },
+}
+
+impl Operation {
+    fn inverse(self) -> Self {
+        match self {
+            Operation::InsertChar { row, col, char } => Operation::DeleteChar { row, col, char },
+            Operation::DeleteChar { row, col, char } => Operation::InsertChar { row, col, char },
+            Operation::SplitLine { row, col } => Operation::JoinLine { row, col },
+            Operation::JoinLine { row, col } => Operation::SplitLine { row, col },
+            Operation::InsertRow { row } => Operation::DeleteRow { row },
+            Operation::DeleteRow { row } => Operation::InsertRow { row },
+        }
+    }
+}
+
+/// Which kind of editing an operation is part of. Consecutive operations of
+/// the same kind are undone together, except for `Other`.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum EditKind {
+    Typing,
+    Deleting,
+    Other,
+}
+
+struct Edit {
+    operation: Operation,
+    cursor_before: (usize, usize),
+    cursor_after: (usize, usize),
+}
+
+/// The edits undone or redone by a single Ctrl-Z or Ctrl-Y.
+struct UndoStep {
+    id: u64,
+    kind: EditKind,
+    edits: Vec<Edit>,
+}
+
+impl UndoStep {
+    fn memory(&self) -> usize {
+        mem::size_of::<Self>() + self.edits.capacity() * mem::size_of::<Edit>()
+    }
+}
+
+pub(crate) struct UndoHistory {
+    undo: VecDeque<UndoStep>,
+    redo: Vec<UndoStep>,
+    // Whether the next edit has to start a new step
+    sealed: bool,
+    next_id: u64,
+    // The state the buffer is in when everything has been undone
+    base_id: u64,
+    saved_id: u64,
+    memory: usize,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            sealed: true,
+            next_id: 1,
+            base_id: 0,
+            saved_id: 0,
+            memory: 0,
+        }
+    }
+}
+
+impl UndoHistory {
+    fn current_id(&self) -> u64 {
+        self.undo.back().map_or(self.base_id, |step| step.id)
+    }
+
+    /// Makes the next edit start a new undo step, for example after the
+    /// cursor was moved.
+    pub(crate) fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    pub(crate) fn mark_saved(&mut self) {
+        self.saved_id = self.current_id();
+        self.seal();
+    }
+
+    /// Records that the file on disk no longer matches any state in the
+    /// history, for example because its format was converted.
+    pub(crate) fn mark_unsaved(&mut self) {
+        self.saved_id = u64::MAX;
+    }
+
+    pub(crate) fn is_at_saved(&self) -> bool {
+        self.current_id() == self.saved_id
+    }
+
+    fn record(&mut self, kind: EditKind, edit: Edit) {
+        for step in self.redo.drain(..) {
+            self.memory -= step.memory();
+        }
+
+        let mergeable = match self.undo.back() {
+            Some(step) => {
+                !self.sealed
+                    && kind != EditKind::Other
+                    && step.kind == kind
+                    && step.edits.last().map(|last| last.cursor_after) == Some(edit.cursor_before)
+            }
+            None => false,
+        };
+
+        if mergeable {
+            let step = self.undo.back_mut().unwrap();
+            self.memory -= step.memory();
+            step.edits.push(edit);
+            self.memory += step.memory();
+        } else {
+            let step = UndoStep {
+                id: self.next_id,
+                kind,
+                edits: vec![edit],
+            };
+            self.next_id += 1;
+            self.memory += step.memory();
+            self.undo.push_back(step);
+        }
+        self.sealed = false;
+
+        while self.memory > UNDO_MEMORY_LIMIT && self.undo.len() > 1 {
+            let step = self.undo.pop_front().unwrap();
+            self.memory -= step.memory();
+            self.base_id = step.id;
+        }
+    }
+}
+
+impl Buffer {
+    /// Applies an operation to the rows, moves the cursor and records it so
+    /// it can be undone.
+    pub(crate) fn edit(
+        &mut self,
+        view: &mut View,
+        kind: EditKind,
+        operation: Operation,
+        cursor_after: (usize, usize),
+    ) {
+        let cursor_before = (view.cursor_row, view.cursor_col);
+        self.apply_operation(operation);
+        view.set_cursor(cursor_after);
+        self.undo_history.record(
+            kind,
+            Edit {
+                operation,
+                cursor_before,
+                cursor_after,
+            },
+        );
+        self.dirty = true;
+    }
+
+    fn apply_operation(&mut self, operation: Operation) {
+        // The row the edit starts at, and how many rows it adds or removes
+        let (row, inserted) = match operation {
+            Operation::InsertChar { row, col, char } => {
+                self.text.insert_char(row, col, char);
+                (row, 0)
+            }
+            Operation::DeleteChar { row, col, .. } => {
+                self.text.delete_char(row, col);
+                (row, 0)
+            }
+            Operation::SplitLine { row, col } => {
+                self.text.split_row(row, col);
+                (row, 1)
+            }
+            Operation::JoinLine { row, .. } => {
+                self.text.join_rows(row);
+                (row, -1)
+            }
+            Operation::InsertRow { row } => {
+                self.text.insert_row(row);
+                (row, 1)
+            }
+            Operation::DeleteRow { row } => {
+                self.text.delete_row(row);
+                (row, -1)
+            }
+        };
+        self.highlight_edited(row, inserted);
+    }
+
+    /// Returns false if there was nothing to undo.
+    pub(crate) fn undo(&mut self, view: &mut View) -> bool {
+        let step = match self.undo_history.undo.pop_back() {
+            Some(step) => step,
+            None => return false,
+        };
+
+        for edit in step.edits.iter().rev() {
+            self.apply_operation(edit.operation.inverse());
+        }
+        view.set_cursor(step.edits[0].cursor_before);
+        self.undo_history.redo.push(step);
+        self.undo_history.seal();
+        self.dirty = !self.undo_history.is_at_saved();
+        true
+    }
+
+    /// Returns false if there was nothing to redo.
+    pub(crate) fn redo(&mut self, view: &mut View) -> bool {
+        let step = match self.undo_history.redo.pop() {
+            Some(step) => step,
+            None => return false,
+        };
+
+        for edit in &step.edits {
+            self.apply_operation(edit.operation);
+        }
+        view.set_cursor(step.edits[step.edits.len() - 1].cursor_after);
+        self.undo_history.undo.push_back(step);
+        self.undo_history.seal();
+        self.dirty = !self.undo_history.is_at_saved();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::Direction;
+
+    fn type_text(buffer: &mut Buffer, view: &mut View, text: &str) {
+        for char in text.chars() {
+            buffer.insert_char(view, char);
+        }
+    }
+
+    #[test]
+    fn moving_the_cursor_starts_a_new_step() {
+        let mut buffer = Buffer::new();
+        let mut view = View::default();
+        type_text(&mut buffer, &mut view, "ab");
+        buffer.move_cursor(&mut view, Direction::Left);
+        type_text(&mut buffer, &mut view, "cd");
+        assert_eq!(buffer.text.row(0), "acdb");
+
+        assert!(buffer.undo(&mut view));
+        assert_eq!(buffer.text.row(0), "ab");
+        assert_eq!((view.cursor_row, view.cursor_col), (0, 1));
+        assert!(buffer.undo(&mut view));
+        assert_eq!(buffer.row_count(), 0);
+        assert!(!buffer.undo(&mut view));
+
+        assert!(buffer.redo(&mut view));
+        assert!(buffer.redo(&mut view));
+        assert_eq!(buffer.text.row(0), "acdb");
+        assert_eq!((view.cursor_row, view.cursor_col), (0, 3));
+        assert!(!buffer.redo(&mut view));
+    }
+
+    #[test]
+    fn undoing_back_to_the_save_cleans_the_buffer() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut buffer = Buffer::new();
+        let mut view = View::default();
+        buffer.file_name = Some(dir.path().join("file.txt").to_string_lossy().into_owned());
+        type_text(&mut buffer, &mut view, "a");
+        buffer.save().unwrap();
+        assert!(!buffer.dirty);
+
+        type_text(&mut buffer, &mut view, "b");
+        assert!(buffer.dirty);
+        buffer.undo(&mut view);
+        assert!(!buffer.dirty);
+        // Going back past the save makes it dirty again
+        buffer.undo(&mut view);
+        assert!(buffer.dirty);
+        buffer.redo(&mut view);
+        assert!(!buffer.dirty);
+
+        // Save, undo, redo is back at what was saved
+        buffer.redo(&mut view);
+        buffer.save().unwrap();
+        buffer.undo(&mut view);
+        assert!(buffer.dirty);
+        buffer.redo(&mut view);
+        assert!(!buffer.dirty);
+        assert_eq!(buffer.text.row(0), "ab");
+    }
+
+    #[test]
+    fn editing_drops_the_redo_history() {
+        let mut buffer = Buffer::new();
+        let mut view = View::default();
+        type_text(&mut buffer, &mut view, "ab");
+        buffer.undo(&mut view);
+        assert_eq!(buffer.undo_history.redo.len(), 1);
+
+        type_text(&mut buffer, &mut view, "c");
+        assert!(buffer.undo_history.redo.is_empty());
+        assert!(!buffer.redo(&mut view));
+        assert_eq!(buffer.text.row(0), "c");
+    }
+
+    #[test]
+    fn forgets_the_oldest_steps_past_the_memory_limit() {
+        let mut history = UndoHistory::default();
+        let edit = |col| Edit {
+            operation: Operation::InsertChar {
+                row: 0,
+                col,
+                char: 'x',
+            },
+            cursor_before: (0, col),
+            cursor_after: (0, col + 1),
+        };
+        let mut recorded = 0;
+        while history.base_id == 0 {
+            history.record(EditKind::Other, edit(recorded));
+            recorded += 1;
+        }
+
+        assert!(history.memory <= UNDO_MEMORY_LIMIT);
+        assert_eq!(history.undo.len(), recorded - 1);
+        assert_eq!(history.base_id, 1);
+        assert_eq!(history.undo.front().unwrap().id, 2);
+        // The saved state went with the forgotten step, so undoing all the
+        // way back is still unsaved
+        while history.undo.pop_back().is_some() {}
+        assert!(!history.is_at_saved());
+    }
+}
@@ -0,0 +1,449 @@
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+
+use crate::{buffer::Buffer, EditorState};
+
+const MIN_SPLIT_PERCENT: u16 = 10;
+const RESIZE_STEP_PERCENT: u16 = 5;
+
+/// A cursor and scroll position in a buffer.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct View {
+    pub(crate) cursor_row: usize,
+    pub(crate) cursor_col: usize,
+    pub(crate) render_col: usize,
+    pub(crate) preferred_render_col: Option<usize>,
+    pub(crate) row_offset: usize,
+    pub(crate) col_offset: usize,
+    /// Where a selection started, with the cursor at its other end.
+    pub(crate) anchor: Option<(usize, usize)>,
+    /// Whether the mouse wheel scrolled the view, which then doesn't follow
+    /// the cursor until it moves.
+    pub(crate) free_scroll: bool,
+}
+
+impl View {
+    pub(crate) fn set_cursor(&mut self, (row, col): (usize, usize)) {
+        self.cursor_row = row;
+        self.cursor_col = col;
+    }
+
+    /// The start and end of the selection, if anything is selected.
+    pub(crate) fn selection(&self) -> Option<((usize, usize), (usize, usize))> {
+        let cursor = (self.cursor_row, self.cursor_col);
+        match self.anchor {
+            Some(anchor) if anchor < cursor => Some((anchor, cursor)),
+            Some(anchor) if anchor > cursor => Some((cursor, anchor)),
+            _ => None,
+        }
+    }
+
+    /// Keeps the cursor inside the buffer, which another window showing
+    /// the same buffer may have made shorter.
+    pub(crate) fn clamp(&mut self, buffer: &Buffer) {
+        if self.cursor_row > buffer.row_count() {
+            self.cursor_row = buffer.row_count();
+        }
+        let row_length = if self.cursor_row < buffer.row_count() {
+            buffer.text.row_len(self.cursor_row)
+        } else {
+            0
+        };
+        if self.cursor_col > row_length {
+            self.cursor_col = row_length;
+        }
+    }
+
+    /// Moves the view so the cursor is inside a `screen_rows` by
+    /// `screen_cols` area. An empty area still scrolls to the cursor, as if
+    /// it had room for one cell.
+    pub(crate) fn scroll(&mut self, buffer: &Buffer, screen_rows: usize, screen_cols: usize) {
+        self.clamp(buffer);
+        let screen_rows = screen_rows.max(1);
+        let screen_cols = screen_cols.max(1);
+
+        if self.free_scroll {
+            self.row_offset = self.row_offset.min(buffer.row_count());
+        } else {
+            if self.cursor_row < self.row_offset {
+                self.row_offset = self.cursor_row
+            }
+            if self.cursor_row >= self.row_offset + screen_rows {
+                self.row_offset = self.cursor_row - screen_rows + 1;
+            }
+        }
+
+        let row = buffer.row(self.cursor_row);
+        self.render_col = row
+            .as_ref()
+            .map_or(0, |row| row.render_col(self.cursor_col));
+        // The whole of a wide grapheme under the cursor has to be visible
+        let cursor_width = match row {
+            Some(row) if self.render_col < row.render_len() => row.cell_width(self.render_col),
+            _ => 1,
+        };
+
+        if self.render_col < self.col_offset {
+            self.col_offset = self.render_col;
+        }
+        if self.render_col + cursor_width > self.col_offset + screen_cols {
+            // Too narrow for the whole grapheme, the start of it wins
+            self.col_offset = (self.render_col + cursor_width)
+                .saturating_sub(screen_cols)
+                .min(self.render_col);
+        }
+    }
+}
+
+/// A viewport showing one buffer.
+pub(crate) struct Window {
+    pub(crate) buffer: usize,
+    pub(crate) view: View,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) struct Rect {
+    pub(crate) x: u16,
+    pub(crate) y: u16,
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+}
+
+impl Rect {
+    pub(crate) fn contains(&self, x: u16, y: u16) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum SplitDirection {
+    /// One window above the other.
+    Horizontal,
+    /// Windows side by side, with a one column separator between them.
+    Vertical,
+}
+
+/// How the screen is divided between the windows. Leaves are indices into
+/// `EditorState::windows`.
+pub(crate) enum Layout {
+    Window(usize),
+    Split {
+        direction: SplitDirection,
+        /// How much of the space the first child gets.
+        percent: u16,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    /// The area of every window when the layout fills `area`.
+    pub(crate) fn window_rects(&self, area: Rect) -> Vec<(usize, Rect)> {
+        let mut rects = Vec::new();
+        self.collect_rects(area, &mut rects, &mut Vec::new());
+        rects
+    }
+
+    /// The columns between side by side windows, as (x, y, height).
+    pub(crate) fn separators(&self, area: Rect) -> Vec<(u16, u16, u16)> {
+        let mut separators = Vec::new();
+        self.collect_rects(area, &mut Vec::new(), &mut separators);
+        separators
+    }
+
+    fn collect_rects(
+        &self,
+        area: Rect,
+        rects: &mut Vec<(usize, Rect)>,
+        separators: &mut Vec<(u16, u16, u16)>,
+    ) {
+        match self {
+            Layout::Window(window) => rects.push((*window, area)),
+            Layout::Split {
+                direction,
+                percent,
+                first,
+                second,
+            } => {
+                let (first_area, second_area) = split_rect(area, *direction, *percent);
+                if *direction == SplitDirection::Vertical && area.width > 0 {
+                    separators.push((first_area.x + first_area.width, area.y, area.height));
+                }
+                first.collect_rects(first_area, rects, separators);
+                second.collect_rects(second_area, rects, separators);
+            }
+        }
+    }
+
+    fn contains(&self, window: usize) -> bool {
+        match self {
+            Layout::Window(index) => *index == window,
+            Layout::Split { first, second, .. } => {
+                first.contains(window) || second.contains(window)
+            }
+        }
+    }
+
+    /// Replaces the leaf for `window` with a split between it and
+    /// `new_window`.
+    fn split(&mut self, window: usize, new_window: usize, direction: SplitDirection) {
+        match self {
+            Layout::Window(index) if *index == window => {
+                *self = Layout::Split {
+                    direction,
+                    percent: 50,
+                    first: Box::new(Layout::Window(window)),
+                    second: Box::new(Layout::Window(new_window)),
+                };
+            }
+            Layout::Window(_) => {}
+            Layout::Split { first, second, .. } => {
+                first.split(window, new_window, direction);
+                second.split(window, new_window, direction);
+            }
+        }
+    }
+
+    /// Removes the leaf for `window`, giving its space to its sibling, and
+    /// renumbers the windows after it.
+    fn remove(&mut self, window: usize) {
+        if let Layout::Split { first, second, .. } = self {
+            if matches!(**first, Layout::Window(index) if index == window) {
+                *self = std::mem::replace(second, Layout::Window(0));
+            } else if matches!(**second, Layout::Window(index) if index == window) {
+                *self = std::mem::replace(first, Layout::Window(0));
+            } else {
+                first.remove(window);
+                second.remove(window);
+                return;
+            }
+        }
+        self.renumber(window);
+    }
+
+    fn renumber(&mut self, removed: usize) {
+        match self {
+            Layout::Window(index) => {
+                if *index > removed {
+                    *index -= 1;
+                }
+            }
+            Layout::Split { first, second, .. } => {
+                first.renumber(removed);
+                second.renumber(removed);
+            }
+        }
+    }
+
+    /// Grows or shrinks `window` inside the closest split in `direction`
+    /// that contains it. Returns false if there is no such split.
+    fn resize(&mut self, window: usize, direction: SplitDirection, grow: bool) -> bool {
+        match self {
+            Layout::Window(_) => false,
+            Layout::Split {
+                direction: split_direction,
+                percent,
+                first,
+                second,
+            } => {
+                if first.resize(window, direction, grow) || second.resize(window, direction, grow) {
+                    return true;
+                }
+
+                let in_first = first.contains(window);
+                if *split_direction != direction || !(in_first || second.contains(window)) {
+                    return false;
+                }
+                *percent = if in_first == grow {
+                    (*percent + RESIZE_STEP_PERCENT).min(100 - MIN_SPLIT_PERCENT)
+                } else {
+                    percent
+                        .saturating_sub(RESIZE_STEP_PERCENT)
+                        .max(MIN_SPLIT_PERCENT)
+                };
+                true
+            }
+        }
+    }
+}
+
+fn split_rect(area: Rect, direction: SplitDirection, percent: u16) -> (Rect, Rect) {
+    match direction {
+        SplitDirection::Horizontal => {
+            let first_height = (area.height as u32 * percent as u32 / 100) as u16;
+            (
+                Rect {
+                    height: first_height,
+                    ..area
+                },
+                Rect {
+                    y: area.y + first_height,
+                    height: area.height - first_height,
+                    ..area
+                },
+            )
+        }
+        SplitDirection::Vertical => {
+            // One column goes to the separator
+            let available = area.width.saturating_sub(1);
+            let first_width = (available as u32 * percent as u32 / 100) as u16;
+            (
+                Rect {
+                    width: first_width,
+                    ..area
+                },
+                Rect {
+                    x: area.x + first_width + 1,
+                    width: available - first_width,
+                    ..area
+                },
+            )
+        }
+    }
+}
+
+enum Focus {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl EditorState {
+    /// The area shared by all windows, everything above the message bar.
+    pub(crate) fn layout_area(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            width: self.screen_cols,
+            height: self.screen_rows.saturating_sub(1),
+        }
+    }
+
+    /// How many rows of text the focused window shows.
+    pub(crate) fn window_text_rows(&self) -> usize {
+        self.layout
+            .window_rects(self.layout_area())
+            .iter()
+            .find(|(index, _)| *index == self.focused_window)
+            .map_or(0, |(_, rect)| rect.height.saturating_sub(1) as usize)
+    }
+
+    /// Reads the key after Ctrl-W and runs the window command it names.
+    pub(crate) fn window_command(&mut self) -> crossterm::Result<()> {
+        self.set_status_message(String::from(
+            "Ctrl-W: s/v = split | w = next | arrows = focus | +/- </> = resize | c = close | o = only",
+        ));
+        self.refresh_screen()?;
+
+        let key = loop {
+            match self.terminal.read()? {
+                Event::Key(key) => break key,
+                Event::Resize(columns, rows) => {
+                    self.resize(columns, rows);
+                    self.refresh_screen()?;
+                }
+                Event::Mouse(_) => {}
+            }
+        };
+        self.set_status_message(String::new());
+
+        let KeyEvent { code, modifiers } = key;
+        // Ctrl-W Ctrl-S works the same as Ctrl-W s, like in vim
+        let code = match code {
+            KeyCode::Char(char) if modifiers.contains(KeyModifiers::CONTROL) => {
+                KeyCode::Char(char.to_ascii_lowercase())
+            }
+            code => code,
+        };
+        match code {
+            KeyCode::Char('s') => self.split_window(SplitDirection::Horizontal),
+            KeyCode::Char('v') => self.split_window(SplitDirection::Vertical),
+            KeyCode::Char('w') => {
+                self.focused_window = (self.focused_window + 1) % self.windows.len()
+            }
+            KeyCode::Left | KeyCode::Char('h') => self.move_focus(Focus::Left),
+            KeyCode::Down | KeyCode::Char('j') => self.move_focus(Focus::Down),
+            KeyCode::Up | KeyCode::Char('k') => self.move_focus(Focus::Up),
+            KeyCode::Right | KeyCode::Char('l') => self.move_focus(Focus::Right),
+            KeyCode::Char('+') => self.resize_window(SplitDirection::Horizontal, true),
+            KeyCode::Char('-') => self.resize_window(SplitDirection::Horizontal, false),
+            KeyCode::Char('>') => self.resize_window(SplitDirection::Vertical, true),
+            KeyCode::Char('<') => self.resize_window(SplitDirection::Vertical, false),
+            KeyCode::Char('c') | KeyCode::Char('q') => self.close_window(),
+            KeyCode::Char('o') => self.only_window(),
+            KeyCode::Esc => {}
+            _ => self.set_status_message(String::from("Unknown window command")),
+        }
+
+        self.update_title()
+    }
+
+    fn split_window(&mut self, direction: SplitDirection) {
+        let window = &self.windows[self.focused_window];
+        let new_window = Window {
+            buffer: window.buffer,
+            view: window.view,
+        };
+        self.windows.push(new_window);
+        let new_index = self.windows.len() - 1;
+        self.layout.split(self.focused_window, new_index, direction);
+        self.focused_window = new_index;
+    }
+
+    fn close_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.set_status_message(String::from("Can't close the last window"));
+            return;
+        }
+
+        let closed = self.focused_window;
+        let window = self.windows.remove(closed);
+        self.buffers[window.buffer].last_view = window.view;
+        self.layout.remove(closed);
+        self.focused_window = closed.min(self.windows.len() - 1);
+    }
+
+    fn only_window(&mut self) {
+        let focused = self.windows.swap_remove(self.focused_window);
+        for window in self.windows.drain(..) {
+            self.buffers[window.buffer].last_view = window.view;
+        }
+        self.windows.push(focused);
+        self.focused_window = 0;
+        self.layout = Layout::Window(0);
+    }
+
+    fn resize_window(&mut self, direction: SplitDirection, grow: bool) {
+        if !self.layout.resize(self.focused_window, direction, grow) {
+            self.set_status_message(String::from("No split to resize"));
+        }
+    }
+
+    /// Focuses the window next to the focused one, on the side given, lined
+    /// up with the cursor.
+    fn move_focus(&mut self, focus: Focus) {
+        let rects = self.layout.window_rects(self.layout_area());
+        let rect = match rects
+            .iter()
+            .find(|(window, _)| *window == self.focused_window)
+        {
+            Some((_, rect)) => *rect,
+            None => return,
+        };
+        let view = &self.windows[self.focused_window].view;
+        let cursor_x = rect.x + view.render_col.saturating_sub(view.col_offset) as u16;
+        let cursor_y = rect.y + view.cursor_row.saturating_sub(view.row_offset) as u16;
+
+        let (x, y) = match focus {
+            // Skip over the separator column
+            Focus::Left if rect.x >= 2 => (rect.x - 2, cursor_y),
+            Focus::Right => (rect.x + rect.width + 1, cursor_y),
+            Focus::Up if rect.y >= 1 => (cursor_x, rect.y - 1),
+            Focus::Down => (cursor_x, rect.y + rect.height),
+            _ => return,
+        };
+        if let Some((window, _)) = rects.iter().find(|(_, rect)| rect.contains(x, y)) {
+            self.focused_window = *window;
+        }
+    }
+}